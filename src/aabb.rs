@@ -1,4 +1,10 @@
-use crate::{interval::Interval, ray::Ray, vec3::Point3};
+use std::ops::Add;
+
+use crate::{
+    interval::Interval,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
 
 #[derive(Default, Clone, Copy)]
 pub struct Aabb {
@@ -30,6 +36,37 @@ impl Aabb {
         Self::from_intervals(x, y, z)
     }
 
+    pub fn pad_to_minimums(self) -> Self {
+        // Adjust the AABB so that no side is narrower than some delta, padding if necessary. Flat
+        // primitives like axis aligned triangles would otherwise get boxes rays can slip past.
+        let delta = 0.0001;
+        let pad = |i: Interval| if i.size() < delta { i.expand(delta) } else { i };
+
+        Self::from_intervals(pad(self.x), pad(self.y), pad(self.z))
+    }
+
+    pub fn min(self) -> Point3 {
+        Point3::new(self.x.min, self.y.min, self.z.min)
+    }
+
+    pub fn max(self) -> Point3 {
+        Point3::new(self.x.max, self.y.max, self.z.max)
+    }
+
+    pub fn corners(self) -> [Point3; 8] {
+        let (lo, hi) = (self.min(), self.max());
+        [
+            Point3::new(lo.x(), lo.y(), lo.z()),
+            Point3::new(hi.x(), lo.y(), lo.z()),
+            Point3::new(lo.x(), hi.y(), lo.z()),
+            Point3::new(hi.x(), hi.y(), lo.z()),
+            Point3::new(lo.x(), lo.y(), hi.z()),
+            Point3::new(hi.x(), lo.y(), hi.z()),
+            Point3::new(lo.x(), hi.y(), hi.z()),
+            Point3::new(hi.x(), hi.y(), hi.z()),
+        ]
+    }
+
     pub fn axis(self, n: i32) -> Interval {
         match n {
             1 => self.y,
@@ -65,3 +102,15 @@ impl Aabb {
         true
     }
 }
+
+impl Add<Vec3> for Aabb {
+    type Output = Self;
+
+    fn add(self, offset: Vec3) -> Self::Output {
+        Self::from_intervals(
+            self.x + offset.x(),
+            self.y + offset.y(),
+            self.z + offset.z(),
+        )
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    ops::RangeInclusive,
+};
+
+use crate::{
+    bvh::BvhNode,
+    camera::Camera,
+    hittable::{AnyHittable, Translate},
+    hittable_list::HittableList,
+    quat::Quat,
+    transform::{MotionTransform, Transform},
+    vec3::{Point3, Vec3},
+};
+
+// Values that can be keyframed, with how they blend from one to another.
+pub trait Keyable: Copy {
+    fn lerp(self, other: Self, t: f64) -> Self;
+
+    // The value moved by a fraction of the change from `from` to `to`, which places the control
+    // points of Bezier curves.
+    fn offset(self, from: Self, to: Self, fraction: f64) -> Self;
+}
+
+impl Keyable for f64 {
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+
+    fn offset(self, from: Self, to: Self, fraction: f64) -> Self {
+        self + (to - from) * fraction
+    }
+}
+
+impl Keyable for Vec3 {
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+
+    fn offset(self, from: Self, to: Self, fraction: f64) -> Self {
+        self + (to - from) * fraction
+    }
+}
+
+impl Keyable for Quat {
+    // Rotations blend along the shortest arc, and are offset by a fraction of the rotation
+    // taking `from` to `to`.
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self.slerp(other, t)
+    }
+
+    fn offset(self, from: Self, to: Self, fraction: f64) -> Self {
+        Quat::identity().slerp(to * from.conjugate(), fraction) * self
+    }
+}
+
+// How the value of a keyframe blends into the value of the next keyframe.
+#[derive(Clone, Copy)]
+pub enum Interpolation {
+    Linear,
+    // Cubic Bezier curve with control points derived from the neighbouring keyframes
+    // (Catmull-Rom tangents), giving smooth motion through every keyframe.
+    Bezier,
+}
+
+#[derive(Clone, Copy)]
+pub struct Keyframe<T> {
+    pub frame: f64,
+    pub value: T,
+    pub interpolation: Interpolation,
+}
+
+#[derive(Clone)]
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>,
+}
+
+impl<T: Keyable> Track<T> {
+    pub fn new() -> Self {
+        Self { keys: vec![] }
+    }
+
+    pub fn add(&mut self, frame: f64, value: T, interpolation: Interpolation) {
+        // Keep the keyframes sorted by frame.
+        let index = self.keys.partition_point(|key| key.frame <= frame);
+        self.keys.insert(
+            index,
+            Keyframe {
+                frame,
+                value,
+                interpolation,
+            },
+        );
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn sample(&self, frame: f64) -> Option<T> {
+        // Returns the value of the track at the given (fractional) frame, holding the first and
+        // last keyframes outside of the keyed range, or None if the track has no keyframes.
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        if frame <= first.frame {
+            return Some(first.value);
+        }
+        if frame >= last.frame {
+            return Some(last.value);
+        }
+
+        let i = self.keys.partition_point(|key| key.frame <= frame) - 1;
+        let k0 = self.keys[i];
+        let k1 = self.keys[i + 1];
+        let t = (frame - k0.frame) / (k1.frame - k0.frame);
+
+        let value = match k0.interpolation {
+            Interpolation::Linear => k0.value.lerp(k1.value, t),
+            Interpolation::Bezier => {
+                let prev = self.keys[i.saturating_sub(1)].value;
+                let next = self.keys[(i + 2).min(self.keys.len() - 1)].value;
+                let c0 = k0.value.offset(prev, k1.value, 1. / 6.);
+                let c1 = k1.value.offset(k0.value, next, -1. / 6.);
+                bezier(k0.value, c0, c1, k1.value, t)
+            }
+        };
+
+        Some(value)
+    }
+}
+
+fn bezier<T: Keyable>(p0: T, p1: T, p2: T, p3: T, t: f64) -> T {
+    // Evaluate the cubic Bezier curve with de Casteljau's algorithm.
+    let a = p0.lerp(p1, t);
+    let b = p1.lerp(p2, t);
+    let c = p2.lerp(p3, t);
+    a.lerp(b, t).lerp(b.lerp(c, t), t)
+}
+
+// Keyframed transform of an object, applied as scale, then rotation, then translation. Empty
+// tracks leave the object where it is, unrotated and at its own size.
+#[derive(Clone)]
+pub struct ObjectTracks {
+    pub translation: Track<Vec3>,
+    pub rotation: Track<Quat>,
+    pub scale: Track<Vec3>,
+}
+
+impl ObjectTracks {
+    pub fn new() -> Self {
+        Self {
+            translation: Track::new(),
+            rotation: Track::new(),
+            scale: Track::new(),
+        }
+    }
+
+    fn transform(&self, frame: f64) -> Transform {
+        Transform::new(
+            self.translation.sample(frame).unwrap_or_default(),
+            self.rotation.sample(frame).unwrap_or_else(Quat::identity),
+            self.scale.sample(frame).unwrap_or(Vec3::new(1., 1., 1.)),
+        )
+    }
+}
+
+struct AnimatedObject {
+    object: AnyHittable,
+    tracks: ObjectTracks,
+}
+
+pub struct Animation {
+    // Keyframed camera parameters, set once per frame from the middle of the shutter interval.
+    // Empty tracks leave the camera setting untouched
+    pub lookfrom: Track<Point3>,
+    pub lookat: Track<Point3>,
+    pub vfov: Track<f64>,
+    // Shutter open and close times as fractions of a frame (defaults to a 180° shutter)
+    pub shutter_open: Track<f64>,
+    pub shutter_close: Track<f64>,
+
+    // Objects that don't move during the animation
+    world: HittableList,
+    // Objects with keyframed transforms
+    objects: Vec<AnimatedObject>,
+}
+
+impl Animation {
+    pub fn new(world: HittableList) -> Self {
+        Self {
+            lookfrom: Track::new(),
+            lookat: Track::new(),
+            vfov: Track::new(),
+            shutter_open: Track::new(),
+            shutter_close: Track::new(),
+            world,
+            objects: vec![],
+        }
+    }
+
+    pub fn add(&mut self, object: impl Into<AnyHittable>, tracks: ObjectTracks) {
+        self.objects.push(AnimatedObject {
+            object: object.into(),
+            tracks,
+        });
+    }
+
+    pub fn render(
+        &self,
+        cam: &mut Camera,
+        frames: RangeInclusive<i32>,
+        path_prefix: &str,
+    ) -> io::Result<()> {
+        // Render every frame from the first to the last to `<path_prefix><frame>.ppm`. Objects
+        // blur with their motion over the shutter interval, but the camera holds still at its
+        // position at the middle of it, so camera motion doesn't blur.
+        for frame in frames {
+            let f = frame as f64;
+            let world = self.frame_world(f, f + 1.);
+
+            // Ray times in [0,1] span the whole frame, the shutter is open for part of it.
+            cam.shutter_open = self.shutter_open.sample(f).unwrap_or(0.);
+            cam.shutter_close = self.shutter_close.sample(f).unwrap_or(0.5);
+
+            // The camera is placed at the middle of the shutter interval.
+            let mid = f + 0.5 * (cam.shutter_open + cam.shutter_close);
+            if let Some(lookfrom) = self.lookfrom.sample(mid) {
+                cam.lookfrom = lookfrom;
+            }
+            if let Some(lookat) = self.lookat.sample(mid) {
+                cam.lookat = lookat;
+            }
+            if let Some(vfov) = self.vfov.sample(mid) {
+                cam.vfov = vfov;
+            }
+
+            eprintln!("Rendering frame {frame}");
+            let file = File::create(format!("{path_prefix}{frame:04}.ppm"))?;
+            cam.render_to(&world, BufWriter::new(file))?;
+        }
+
+        Ok(())
+    }
+
+    fn frame_world(&self, frame_start: f64, frame_end: f64) -> HittableList {
+        // Build the world for a single frame. Ray times in [0,1] span the frame, so each animated
+        // object moves between its transforms at the start and end of the frame.
+        let mut world = self.world.clone();
+
+        for animated in &self.objects {
+            let tracks = &animated.tracks;
+            let object = animated.object.clone();
+            if tracks.rotation.is_empty() && tracks.scale.is_empty() {
+                // Objects that only move are cheaper to intersect with a plain translation.
+                let offset = |frame| tracks.translation.sample(frame).unwrap_or_default();
+                world.add(Translate::moving(
+                    object,
+                    offset(frame_start),
+                    offset(frame_end),
+                ));
+            } else {
+                world.add(MotionTransform::new(
+                    object,
+                    tracks.transform(frame_start),
+                    tracks.transform(frame_end),
+                ));
+            }
+        }
+
+        HittableList::from_hittable(BvhNode::from_list(world))
+    }
+}
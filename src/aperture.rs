@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use image::ImageResult;
+
+use crate::{
+    rtw_image::{ColorSpace, RtwImage},
+    rtweekend::{degrees_to_radians, random_double, random_int_min_max, PI},
+    vec3::Vec3,
+};
+
+// Shape of the lens opening that rays pass through, scaled to the defocus disk.
+#[derive(Clone, Default)]
+pub enum Aperture {
+    // Perfectly round opening
+    #[default]
+    Circle,
+    // Regular polygon formed by the diaphragm blades, rotated counterclockwise in degrees
+    Polygon {
+        blades: i32,
+        rotation: f64,
+    },
+    // Ring left around the central obstruction of a catadioptric (mirror) lens, with the inner
+    // radius as a fraction of the outer radius
+    Annular {
+        inner_radius: f64,
+    },
+    // Arbitrary shape given by a grayscale image
+    Mask(ApertureMask),
+}
+
+impl Aperture {
+    pub fn sample(&self) -> Vec3 {
+        // Returns a random point in the aperture, within the unit square centered on the origin.
+        match self {
+            Self::Circle => Vec3::random_in_unit_disk(),
+            Self::Polygon { blades, rotation } => {
+                // Pick one of the triangles fanning out from the center, then a uniform point in it.
+                let blades = (*blades).max(3);
+                let k = random_int_min_max(0, blades - 1) as f64;
+                let step = 2. * PI / blades as f64;
+                let angle0 = degrees_to_radians(*rotation) + k * step;
+                let a = Vec3::new(angle0.cos(), angle0.sin(), 0.);
+                let b = Vec3::new((angle0 + step).cos(), (angle0 + step).sin(), 0.);
+
+                let mut s = random_double();
+                let mut t = random_double();
+                if s + t > 1. {
+                    (s, t) = (1. - s, 1. - t);
+                }
+                s * a + t * b
+            }
+            Self::Annular { inner_radius } => {
+                let r2 = inner_radius * inner_radius;
+                let r = (r2 + (1. - r2) * random_double()).sqrt();
+                let angle = 2. * PI * random_double();
+                Vec3::new(r * angle.cos(), r * angle.sin(), 0.)
+            }
+            Self::Mask(mask) => mask.sample(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ApertureMask {
+    width: i32,
+    height: i32,
+    // Cumulative distribution of the pixel brightness, ending at 1
+    cdf: Arc<[f64]>,
+}
+
+impl ApertureMask {
+    pub fn new(filename: &str) -> ImageResult<Self> {
+        // Bright pixels let light through, dark pixels block it. The values are transmission
+        // rather than colors, so they're read without the sRGB curve.
+        let image = RtwImage::load(filename, ColorSpace::Linear)?;
+        let (width, height) = (image.width(), image.height());
+
+        let mut total = 0.;
+        let mut cdf = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = image.pixel_data(x, y);
+                total += (pixel[0] + pixel[1] + pixel[2]) as f64 / 3.;
+                cdf.push(total);
+            }
+        }
+
+        if total > 0. {
+            cdf.iter_mut().for_each(|c| *c /= total);
+        } else {
+            // A fully black mask would block everything, treat it as fully open instead.
+            let n = cdf.len() as f64;
+            cdf.iter_mut()
+                .enumerate()
+                .for_each(|(i, c)| *c = (i + 1) as f64 / n);
+        }
+
+        Ok(Self {
+            width,
+            height,
+            cdf: cdf.into(),
+        })
+    }
+
+    fn sample(&self) -> Vec3 {
+        // Pick a pixel with probability proportional to its brightness, then a uniform point in it.
+        let u = random_double();
+        let index = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .min(self.cdf.len() - 1) as i32;
+        let x = (index % self.width) as f64 + random_double();
+        let y = (index / self.width) as f64 + random_double();
+
+        // Fit the image into the unit square, flipping y so the top row of the mask is at the top.
+        let size = self.width.max(self.height) as f64;
+        Vec3::new(
+            (2. * x - self.width as f64) / size,
+            (self.height as f64 - 2. * y) / size,
+            0.,
+        )
+    }
+}
@@ -1,18 +1,113 @@
 use crate::{
+    aperture::Aperture,
     color::{write_color, Color},
     hittable::Hittable,
     hittable_list::HittableList,
     interval::Interval,
+    lens::{FocusedLens, LensSystem, D_LINE_WAVELENGTH},
     material::Material,
-    ray::Ray,
-    rtweekend::{degrees_to_radians, random_double, INFINITY},
+    ray::{Differentials, Ray},
+    rtweekend::{degrees_to_radians, random_double, random_int_min_max, INFINITY, PI},
+    shutter::{ShutterMode, ShutterShape},
+    spectrum::{rgb_to_spectrum, spectrum_to_rgb, LAMBDA_MAX, LAMBDA_MIN},
     vec3::{Point3, Vec3},
 };
-use std::io::stdout;
-use std::{fmt::Write as _, io::Write as _};
+use std::fmt::Write as _;
+use std::io::{self, stdout, Write};
 
 use rayon::prelude::*;
 
+// How the camera maps image positions to ray directions.
+#[derive(Clone, Copy, Default)]
+pub enum Projection {
+    // Pinhole or thin lens perspective, the image height spans the vertical field of view
+    #[default]
+    Perspective,
+    // Parallel rays, the image height spans the viewport height vfov gives at the focus distance
+    Orthographic,
+    // Fisheye where the distance from the image center is proportional to the view angle, the
+    // image height spans the vertical field of view
+    FisheyeEquidistant,
+    // Fisheye preserving solid angles, the image height spans the vertical field of view
+    FisheyeEquisolid,
+    // Full 360° by 180° latitude-longitude panorama, vfov and defocus are ignored
+    Equirectangular,
+}
+
+// How the views of the two eyes are arranged in the rendered image.
+#[derive(Clone, Copy, Default)]
+pub enum StereoLayout {
+    // A single view from the camera center
+    #[default]
+    Mono,
+    // Left eye on the left half, right eye on the right half
+    SideBySide,
+    // Left eye on the top half, right eye on the bottom half
+    TopBottom,
+}
+
+// Photographic description of the camera, from which the field of view, the defocus disk and
+// the exposure are derived. Scene units are taken to be meters.
+#[derive(Clone, Copy)]
+pub struct PhysicalCamera {
+    // Lens focal length in millimeters
+    pub focal_length: f64,
+    // Sensor (or film) width in millimeters, the height follows from the image aspect ratio
+    pub sensor_width: f64,
+    // Aperture f-number (f-stop)
+    pub f_number: f64,
+    // Exposure time in seconds
+    pub shutter_speed: f64,
+    // Sensor sensitivity
+    pub iso: f64,
+    // Lens tilt and swing in degrees, turning the plane of focus by the Scheimpflug principle
+    pub lens_tilt: f64,
+    pub lens_swing: f64,
+}
+
+impl PhysicalCamera {
+    pub fn new(focal_length: f64, f_number: f64) -> Self {
+        // A full frame sensor exposed according to the "sunny 16" rule.
+        Self {
+            focal_length,
+            sensor_width: 36.,
+            f_number,
+            shutter_speed: 1. / 100.,
+            iso: 100.,
+            lens_tilt: 0.,
+            lens_swing: 0.,
+        }
+    }
+
+    fn vfov(self, aspect_ratio: f64) -> f64 {
+        let sensor_height = self.sensor_width / aspect_ratio;
+        2. * (sensor_height / (2. * self.focal_length))
+            .atan()
+            .to_degrees()
+    }
+
+    fn defocus_angle(self, focus_dist: f64) -> f64 {
+        // The entrance pupil diameter is the focal length divided by the f-number.
+        let aperture_radius = self.focal_length / (2. * self.f_number) / 1000.;
+        2. * (aperture_radius / focus_dist).atan().to_degrees()
+    }
+
+    fn focus_plane_angle(self, lens_angle: f64, focus_dist: f64) -> f64 {
+        // By the hinge rule, tilting the lens makes the plane of focus pivot around a line at
+        // distance focal_length / sin(lens_angle) from the lens, while still passing through the
+        // focus distance on the optical axis.
+        let hinge_dist = self.focal_length / 1000. / degrees_to_radians(lens_angle).sin();
+        (focus_dist / hinge_dist).atan().to_degrees()
+    }
+
+    fn exposure(self) -> f64 {
+        // Scale factor applied to the incoming light, calibrated so that the "sunny 16" exposure
+        // (f/16 and 1/100 s at ISO 100) leaves it unchanged.
+        const SUNNY_16: f64 = (1. / 100.) / (16. * 16.);
+        (self.shutter_speed * self.iso / 100.) / (self.f_number * self.f_number) / SUNNY_16
+    }
+}
+
 #[derive(Default)]
 pub struct Camera {
     // Ratio of image width over height
@@ -24,6 +119,8 @@ pub struct Camera {
     // Maximum number of ray bounces into scene
     pub max_depth: i32,
 
+    // Mapping from image positions to rays
+    pub projection: Projection,
     // Vertical view angle (field of view)
     pub vfov: f64,
     // Point camera is looking from
@@ -36,9 +133,56 @@ pub struct Camera {
     pub defocus_angle: f64,
     // Distance from camera lookfrom point to plane of perfect focus
     pub focus_dist: f64,
+    // Rotation of the plane of focus around the horizontal and vertical camera axes in degrees,
+    // positive angles move focus further away towards the top and right of the image
+    pub focus_tilt: f64,
+    pub focus_swing: f64,
+    // Off-center shift of the viewport as a fraction of its width and height
+    pub shift_x: f64,
+    pub shift_y: f64,
+    // Physical camera settings, overriding vfov, defocus_angle, focus_tilt and focus_swing when set
+    pub physical: Option<PhysicalCamera>,
+    // Lens system traced from the film, replacing the projection, defocus, stereo and tilt-shift
+    // settings when set
+    pub lens: Option<LensSystem>,
+    // Shape of the defocus disk, and so of out-of-focus highlights
+    pub aperture: Aperture,
+    // Offset of the lens barrel clipping the aperture towards the image edges, 0 for none
+    pub cat_eye: f64,
+
+    // Arrangement of the stereo views, with the equirectangular projection the two views form an
+    // omnidirectional stereo (ODS) panorama
+    pub stereo: StereoLayout,
+    // Distance between the left and right eye
+    pub interocular_dist: f64,
+    // Distance from camera lookfrom point to the plane of zero parallax
+    pub convergence_dist: f64,
+
+    // Ray time at which the shutter opens
+    pub shutter_open: f64,
+    // Ray time at which the shutter closes
+    pub shutter_close: f64,
+    // Shutter efficiency over the open interval
+    pub shutter_shape: ShutterShape,
+    // Global or rolling shutter
+    pub shutter_mode: ShutterMode,
+
+    // Trace light at single wavelengths and convert to RGB through the CIE matching functions,
+    // so that dispersive materials and lenses split light into its colors
+    pub spectral: bool,
+    // Number of stratified wavelengths traced for each sample in spectral mode
+    pub wavelengths_per_sample: i32,
 
     // Rendered image height
     image_height: i32,
+    // Scale factor applied to the gathered light
+    exposure: f64,
+    // The field of view, defocus angle and focus plane angles rendered with, from the physical
+    // camera when it's set
+    view_vfov: f64,
+    view_defocus_angle: f64,
+    view_focus_tilt: f64,
+    view_focus_swing: f64,
     // Camera center
     center: Point3,
     // Location of pixel 0, 0
@@ -55,6 +199,10 @@ pub struct Camera {
     defocus_disk_u: Vec3,
     // Defocus disk vertical radius
     defocus_disk_v: Vec3,
+    // Normal of the plane of focus
+    focus_normal: Vec3,
+    // Lens system focused at the focus distance
+    focused_lens: Option<FocusedLens>,
 }
 
 impl Camera {
@@ -64,45 +212,71 @@ impl Camera {
             image_width: 100,
             samples_per_pixel: 10,
             max_depth: 10,
+            projection: Projection::Perspective,
             vfov: 90.,
             lookfrom: Point3::new(0., 0., -1.),
             lookat: Point3::new(0., 0., 0.),
             vup: Vec3::new(0., 1., 0.),
             defocus_angle: 0.,
             focus_dist: 10.,
+            focus_tilt: 0.,
+            focus_swing: 0.,
+            shift_x: 0.,
+            shift_y: 0.,
+            physical: None,
+            lens: None,
+            aperture: Aperture::Circle,
+            cat_eye: 0.,
+            stereo: StereoLayout::Mono,
+            interocular_dist: 0.064,
+            convergence_dist: 10.,
+            shutter_open: 0.,
+            shutter_close: 1.,
+            shutter_shape: ShutterShape::Box,
+            shutter_mode: ShutterMode::Global,
+            spectral: false,
+            wavelengths_per_sample: 4,
             ..Default::default()
         }
     }
 
     pub fn render(&mut self, world: &HittableList) {
+        self.render_to(world, stdout().lock()).unwrap();
+    }
+
+    pub fn render_to(&mut self, world: &HittableList, mut out: impl Write) -> io::Result<()> {
         self.initialize();
 
+        // Stereo views are rendered next to each other into a single image.
+        let (output_width, output_height) = match self.stereo {
+            StereoLayout::Mono => (self.image_width, self.image_height),
+            StereoLayout::SideBySide => (2 * self.image_width, self.image_height),
+            StereoLayout::TopBottom => (self.image_width, 2 * self.image_height),
+        };
+
         let mut buf = String::new();
 
-        let _ = writeln!(
-            &mut buf,
-            "P3\n{} {}\n255",
-            self.image_width, self.image_height
-        );
+        let _ = writeln!(&mut buf, "P3\n{output_width} {output_height}\n255");
 
-        let pixels: Vec<_> = (0..self.image_height)
+        let pixels: Vec<_> = (0..output_height)
             .into_par_iter()
-            .flat_map(|j| rayon::iter::repeat(j).zip(0..self.image_width))
+            .flat_map(|j| rayon::iter::repeat(j).zip(0..output_width))
             .map(|(j, i)| {
                 (0..self.samples_per_pixel)
-                    .map(|_| {
-                        let r = self.get_ray(i, j);
-                        Self::ray_color(r, self.max_depth, world)
-                    })
+                    .map(|_| self.sample_color(i, j, world))
                     .fold(Color::new(0., 0., 0.), |a, b| a + b)
             })
             .collect();
 
         for pixel_color in pixels {
-            write_color(&mut buf, pixel_color, self.samples_per_pixel)
+            write_color(
+                &mut buf,
+                self.exposure * pixel_color,
+                self.samples_per_pixel,
+            )
         }
 
-        stdout().lock().write_all(buf.as_bytes()).unwrap();
+        out.write_all(buf.as_bytes())
     }
 
     fn initialize(&mut self) {
@@ -114,8 +288,25 @@ impl Camera {
 
         self.center = self.lookfrom;
 
+        // Derive the field of view, defocus and exposure from the physical camera settings.
+        // The settings themselves are left as they are, so they still apply once the physical
+        // camera is cleared.
+        self.exposure = 1.;
+        self.view_vfov = self.vfov;
+        self.view_defocus_angle = self.defocus_angle;
+        self.view_focus_tilt = self.focus_tilt;
+        self.view_focus_swing = self.focus_swing;
+        if let Some(physical) = self.physical {
+            self.view_vfov = physical.vfov(self.image_width as f64 / self.image_height as f64);
+            self.view_defocus_angle = physical.defocus_angle(self.focus_dist);
+            self.exposure = physical.exposure();
+            self.view_focus_tilt = physical.focus_plane_angle(physical.lens_tilt, self.focus_dist);
+            self.view_focus_swing =
+                physical.focus_plane_angle(physical.lens_swing, self.focus_dist);
+        }
+
         // Determine viewport dimensions.
-        let theta = degrees_to_radians(self.vfov);
+        let theta = degrees_to_radians(self.view_vfov);
         let h = (theta / 2.).tan();
         let viewport_height = 2.0 * h * self.focus_dist;
         let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64);
@@ -135,47 +326,301 @@ impl Camera {
         self.pixel_delta_u = viewport_u / self.image_width as f64;
         self.pixel_delta_v = viewport_v / self.image_height as f64;
 
-        // Calculate the location of the upper left pixel.
+        // Calculate the location of the upper left pixel, shifting the viewport off-center.
         let viewport_upper_left =
-            self.center - (self.focus_dist * self.w) - viewport_u / 2. - viewport_v / 2.;
+            self.center - (self.focus_dist * self.w) - viewport_u / 2. - viewport_v / 2.
+                + self.shift_x * viewport_u
+                - self.shift_y * viewport_v;
         self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
 
         // Calculate the camera defocus disk basis vectors.
-        let defocus_radius = self.focus_dist * degrees_to_radians(self.defocus_angle / 2.).tan();
+        let defocus_radius =
+            self.focus_dist * degrees_to_radians(self.view_defocus_angle / 2.).tan();
         self.defocus_disk_u = self.u * defocus_radius;
         self.defocus_disk_v = self.v * defocus_radius;
+
+        // Calculate the normal of the plane of focus.
+        self.focus_normal = (self.w
+            + degrees_to_radians(self.view_focus_tilt).tan() * self.v
+            + degrees_to_radians(self.view_focus_swing).tan() * self.u)
+            .unit_vector();
+
+        // Focus the lens system, with the film sized like the physical camera sensor.
+        self.focused_lens = self.lens.as_ref().map(|lens| {
+            let sensor_width = self.physical.map_or(36., |physical| physical.sensor_width);
+            let film_width = sensor_width / 1000.;
+            let film_height = film_width * self.image_height as f64 / self.image_width as f64;
+            lens.focus(self.focus_dist, film_width, film_height)
+        });
+    }
+
+    fn sample_color(&self, i: i32, j: i32, world: &HittableList) -> Color {
+        // Gather the light of one sample of the output pixel at location i,j.
+        if !self.spectral {
+            return match self.get_ray(i, j, None) {
+                Some((r, weight)) => weight * Self::ray_color(r, self.max_depth, world),
+                None => Color::new(0., 0., 0.),
+            };
+        }
+
+        // Spread the wavelengths evenly over the visible range, from a random offset.
+        let n = self.wavelengths_per_sample.max(1);
+        let offset = random_double();
+        (0..n)
+            .map(|k| {
+                let lambda =
+                    LAMBDA_MIN + (k as f64 + offset) / n as f64 * (LAMBDA_MAX - LAMBDA_MIN);
+                match self.get_ray(i, j, Some(lambda)) {
+                    Some((r, weight)) => {
+                        let radiance = weight * Self::ray_color(r, self.max_depth, world);
+                        spectrum_to_rgb(radiance.x(), lambda)
+                    }
+                    None => Color::new(0., 0., 0.),
+                }
+            })
+            .fold(Color::new(0., 0., 0.), |a, b| a + b)
+            / n as f64
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        // Get a randomly-sampled camera ray for the pixel at location i,j, originating from
-        // the camera defocus disk.
+    fn get_ray(&self, i: i32, j: i32, wavelength: Option<f64>) -> Option<(Ray, Color)> {
+        // Get a randomly-sampled camera ray for the output pixel at location i,j, originating from
+        // the camera defocus disk, and the weight of the light it gathers. Returns None for pixels
+        // outside of the projection's image.
+
+        let (eye, i, j) = self.eye_pixel(i, j);
+        let x = i as f64 - 0.5 + random_double();
+        let y = j as f64 - 0.5 + random_double();
+        let ray_time = self.sample_time(j);
+
+        if let Some(lens) = &self.focused_lens {
+            return self.lens_ray(lens, x, y, ray_time, wavelength);
+        }
+        let focus_ray = |x: f64, y: f64| {
+            let (origin, focus_point) = self.pinhole_ray(x, y)?;
+            let (origin, focus_point) = if eye == 0. {
+                (origin, focus_point)
+            } else {
+                self.eye_ray(eye, origin, focus_point)
+            };
+            Some((origin, self.tilted_focus_point(origin, focus_point)))
+        };
+        let (origin, focus_point) = focus_ray(x, y)?;
+
+        let ray_origin = match self.projection {
+            Projection::Equirectangular => origin,
+            _ if self.view_defocus_angle <= 0. => origin,
+            _ => origin + self.defocus_disk_sample(x, y)?,
+        };
+        let ray_direction = focus_point - ray_origin;
+
+        // The rays through the next pixels over leave from the same point on the lens.
+        let lens_offset = ray_origin - origin;
+        let neighbor = |x: f64, y: f64| {
+            let (origin, focus_point) = focus_ray(x, y)?;
+            Some((origin + lens_offset, focus_point - origin - lens_offset))
+        };
+        let differentials = match (neighbor(x + 1., y), neighbor(x, y + 1.)) {
+            (Some((rx_origin, rx_direction)), Some((ry_origin, ry_direction))) => {
+                Some(Differentials {
+                    rx_origin,
+                    rx_direction,
+                    ry_origin,
+                    ry_direction,
+                })
+            }
+            _ => None,
+        };
+
+        let r = Ray::new(ray_origin, ray_direction, ray_time).with_differentials(differentials);
+        let r = match wavelength {
+            Some(lambda) => r.with_wavelength(lambda),
+            None => r,
+        };
 
-        let pixel_center =
-            self.pixel00_loc + (i as f64 * self.pixel_delta_u) + (j as f64 * self.pixel_delta_v);
-        let pixel_sample = pixel_center + self.pixel_sample_squre();
+        Some((r, Color::new(1., 1., 1.)))
+    }
 
-        let ray_origin = if self.defocus_angle <= 0. {
-            self.center
+    fn lens_ray(
+        &self,
+        lens: &FocusedLens,
+        x: f64,
+        y: f64,
+        time: f64,
+        spectral_wavelength: Option<f64>,
+    ) -> Option<(Ray, Color)> {
+        // Traces a ray from image position x,y on the film through the lens system. Dispersive
+        // lenses bend each color differently, so outside of spectral mode each ray carries a
+        // single color channel. These rays carry no differentials.
+        let (wavelength, tint) = if let Some(lambda) = spectral_wavelength {
+            (lambda, Color::new(1., 1., 1.))
+        } else if lens.is_dispersive() {
+            match random_int_min_max(0, 2) {
+                0 => (610., Color::new(3., 0., 0.)),
+                1 => (550., Color::new(0., 3., 0.)),
+                _ => (465., Color::new(0., 0., 3.)),
+            }
         } else {
-            self.defocus_disk_sample()
+            (D_LINE_WAVELENGTH, Color::new(1., 1., 1.))
+        };
+
+        let s = (x + 0.5) / self.image_width as f64 - 0.5;
+        let t = (y + 0.5) / self.image_height as f64 - 0.5;
+        let (origin, direction, weight) = lens.sample_ray(s, t, wavelength)?;
+
+        // Camera space looks down +z, with x to the right and y up.
+        let to_world = |v: Vec3| v.x() * self.u + v.y() * self.v - v.z() * self.w;
+        let r = Ray::new(self.center + to_world(origin), to_world(direction), time);
+        let r = match spectral_wavelength {
+            Some(lambda) => r.with_wavelength(lambda),
+            None => r,
         };
-        let ray_direction = pixel_sample - ray_origin;
-        let ray_time = random_double();
 
-        Ray::new(ray_origin, ray_direction, ray_time)
+        Some((r, weight * tint))
+    }
+
+    fn eye_pixel(&self, i: i32, j: i32) -> (f64, i32, i32) {
+        // Split an output pixel location into the eye it belongs to (-1 for left, 1 for right,
+        // 0 for mono) and the pixel location within that eye's view.
+        match self.stereo {
+            StereoLayout::Mono => (0., i, j),
+            StereoLayout::SideBySide if i < self.image_width => (-1., i, j),
+            StereoLayout::SideBySide => (1., i - self.image_width, j),
+            StereoLayout::TopBottom if j < self.image_height => (-1., i, j),
+            StereoLayout::TopBottom => (1., i, j - self.image_height),
+        }
+    }
+
+    fn eye_ray(&self, eye: f64, origin: Point3, focus_point: Point3) -> (Point3, Point3) {
+        // Moves a pinhole ray to one eye, turning it towards the point where the central ray
+        // crosses the convergence distance, so that objects there have zero parallax.
+        let direction = focus_point - origin;
+
+        // The eyes sit on the horizontal axis of the camera. For panoramas they sit on a circle,
+        // at right angles to the horizontal part of the viewing direction.
+        let axis = match self.projection {
+            Projection::Equirectangular => {
+                let forward = direction.dot(-self.w);
+                let right = direction.dot(self.u);
+                let length = (forward * forward + right * right).sqrt();
+                if length > 0. {
+                    (forward * self.u + right * self.w) / length
+                } else {
+                    self.u
+                }
+            }
+            _ => self.u,
+        };
+
+        let convergence_point = origin + direction * (self.convergence_dist / self.focus_dist);
+        let eye_origin = origin + eye * self.interocular_dist / 2. * axis;
+        let eye_direction = convergence_point - eye_origin;
+
+        (
+            eye_origin,
+            eye_origin + eye_direction * (self.focus_dist / self.convergence_dist),
+        )
+    }
+
+    fn tilted_focus_point(&self, origin: Point3, focus_point: Point3) -> Point3 {
+        // Moves the focus point along the ray onto the tilted plane of focus, which passes through
+        // the focus distance on the camera axis.
+        if self.view_focus_tilt == 0. && self.view_focus_swing == 0. {
+            return focus_point;
+        }
+
+        let direction = focus_point - origin;
+        let plane_point = self.center - self.focus_dist * self.w;
+        let denom = direction.dot(self.focus_normal);
+        let t = (plane_point - origin).dot(self.focus_normal) / denom;
+        if denom.abs() < 1e-8 || t <= 0. {
+            // The ray never reaches the plane of focus in front of the camera.
+            return focus_point;
+        }
+
+        origin + t * direction
+    }
+
+    fn pinhole_ray(&self, x: f64, y: f64) -> Option<(Point3, Point3)> {
+        // Returns the origin of the ray through image position x,y (in pixels, relative to the
+        // center of pixel 0,0) and the point it passes through at the focus distance.
+        match self.projection {
+            Projection::Perspective => {
+                let focus_point =
+                    self.pixel00_loc + x * self.pixel_delta_u + y * self.pixel_delta_v;
+                Some((self.center, focus_point))
+            }
+            Projection::Orthographic => {
+                let focus_point =
+                    self.pixel00_loc + x * self.pixel_delta_u + y * self.pixel_delta_v;
+                Some((focus_point + self.focus_dist * self.w, focus_point))
+            }
+            Projection::FisheyeEquidistant | Projection::FisheyeEquisolid => {
+                // Position relative to the image center, scaled so the top edge is at distance 1.
+                let half_height = self.image_height as f64 / 2.;
+                let nx = (x + 0.5 - self.image_width as f64 / 2.) / half_height;
+                let ny = (y + 0.5 - half_height) / half_height;
+                let r = (nx * nx + ny * ny).sqrt();
+
+                let theta_max = degrees_to_radians(self.view_vfov) / 2.;
+                let theta = match self.projection {
+                    Projection::FisheyeEquidistant => r * theta_max,
+                    _ => {
+                        let sin_half_theta = r * (theta_max / 2.).sin();
+                        if sin_half_theta > 1. {
+                            return None;
+                        }
+                        2. * sin_half_theta.asin()
+                    }
+                };
+                if theta > PI {
+                    return None;
+                }
+
+                let phi = ny.atan2(nx);
+                let direction = theta.sin() * phi.cos() * self.u - theta.sin() * phi.sin() * self.v
+                    + theta.cos() * -self.w;
+                Some((self.center, self.center + self.focus_dist * direction))
+            }
+            Projection::Equirectangular => {
+                let longitude = (x + 0.5) / self.image_width as f64 * 2. * PI - PI;
+                let latitude = PI / 2. - (y + 0.5) / self.image_height as f64 * PI;
+
+                let direction = latitude.cos() * longitude.sin() * self.u + latitude.sin() * self.v
+                    - latitude.cos() * longitude.cos() * self.w;
+                Some((self.center, self.center + self.focus_dist * direction))
+            }
+        }
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        // Returns a random point in the camera defocus disk.
-        let p = Vec3::random_in_unit_disk();
-        self.center + (p[0] * self.defocus_disk_u) + (p[1] * self.defocus_disk_v)
+    fn sample_time(&self, j: i32) -> f64 {
+        // Returns a random ray time within the shutter interval of scanline j.
+        let start = match self.shutter_mode {
+            ShutterMode::Global => self.shutter_open,
+            ShutterMode::Rolling { readout } => {
+                self.shutter_open + readout * j as f64 / self.image_height as f64
+            }
+        };
+        start + self.shutter_shape.sample() * (self.shutter_close - self.shutter_open)
     }
 
-    fn pixel_sample_squre(&self) -> Vec3 {
-        // Returns a random point in the square surrounding a pixel at the origin.
-        let px = -0.5 + random_double();
-        let py = -0.5 + random_double();
-        (px * self.pixel_delta_u) + (py * self.pixel_delta_v)
+    fn defocus_disk_sample(&self, x: f64, y: f64) -> Option<Vec3> {
+        // Returns a random offset from the ray origin within the camera defocus disk, or None if
+        // the lens barrel blocks the ray through image position x,y.
+        let p = self.aperture.sample();
+
+        if self.cat_eye > 0. {
+            // The barrel shows up as a second disk, shifted further off-center the further the
+            // image position is from the image center (y flipped to point up like the disk).
+            let half_width = self.image_width as f64 / 2.;
+            let half_height = self.image_height as f64 / 2.;
+            let half_diagonal = (half_width * half_width + half_height * half_height).sqrt();
+            let offset = Vec3::new(x + 0.5 - half_width, half_height - y - 0.5, 0.);
+            if (p - self.cat_eye * offset / half_diagonal).length_squared() > 1. {
+                return None;
+            }
+        }
+
+        Some((p[0] * self.defocus_disk_u) + (p[1] * self.defocus_disk_v))
     }
 
     fn ray_color(r: Ray, depth: i32, world: &HittableList) -> Color {
@@ -184,15 +629,72 @@ impl Camera {
             return Color::new(0., 0., 0.);
         }
 
-        if let Some(rec) = world.hit(r, Interval::new(0.001, INFINITY)) {
+        // Light is absorbed, and may be scattered, along the way by the medium the ray travels
+        // through.
+        let hit = world.hit(r, Interval::new(0.001, INFINITY));
+        let length = r.direction().length();
+        let max_distance = hit.as_ref().map_or(INFINITY, |rec| rec.t * length);
+        // Each color travels its own way through a scattering medium, so the random walk follows
+        // the ray's wavelength in spectral mode, or a single color channel picked at the first
+        // scattering event.
+        let medium = match (r.wavelength(), r.channel()) {
+            (Some(lambda), _) => r.medium().at_wavelength(lambda),
+            (None, Some(channel)) => r.medium().channel(channel),
+            (None, None) => r.medium(),
+        };
+        let (distance, weight) = medium.sample_distance(max_distance);
+
+        if distance < max_distance {
+            // Scattered inside the medium, equally likely in any direction.
+            let scattered = r.scattered(r.at(distance / length), Vec3::random_unit_vector());
+            let (scattered, weight) = if r.wavelength().is_none() && r.channel().is_none() {
+                // Pick the channel by its share of the weight.
+                let total = weight.x() + weight.y() + weight.z();
+                let mut pick = random_double() * total;
+                let mut channel = 2;
+                for c in 0..2 {
+                    if pick < weight[c] {
+                        channel = c;
+                        break;
+                    }
+                    pick -= weight[c];
+                }
+                let mut tint = Color::default();
+                tint[channel] = total;
+                (scattered.with_channel(channel), tint)
+            } else {
+                (scattered, weight)
+            };
+            return Self::at_wavelength(r, weight) * Self::ray_color(scattered, depth - 1, world);
+        }
+
+        if let Some(mut rec) = hit {
+            rec.complete_tangents();
+            rec.compute_differentials(r);
             if let Some((attenuation, scattered)) = rec.mat.scatter(r, &rec) {
-                return attenuation * Self::ray_color(scattered, depth - 1, world);
+                return Self::at_wavelength(r, weight * attenuation)
+                    * Self::ray_color(scattered, depth - 1, world);
             }
             return Color::new(0., 0., 0.);
         }
 
         let unit_direction = r.direction().unit_vector();
         let a = 0.5 * (unit_direction.y() + 1.0);
-        (1.0 - a) * Color::new(1., 1., 1.) + a * Color::new(0.5, 0.7, 1.0)
+        Self::at_wavelength(
+            r,
+            weight * ((1.0 - a) * Color::new(1., 1., 1.) + a * Color::new(0.5, 0.7, 1.0)),
+        )
+    }
+
+    fn at_wavelength(r: Ray, color: Color) -> Color {
+        // Rays of the spectral renderer see the value of the upsampled spectrum of a color at
+        // their wavelength, in all three channels.
+        match r.wavelength() {
+            Some(lambda) => {
+                let value = rgb_to_spectrum(color, lambda);
+                Color::new(value, value, value)
+            }
+            None => color,
+        }
     }
 }
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use enum_dispatch::enum_dispatch;
 
 use crate::{
@@ -6,8 +8,11 @@ use crate::{
     hittable_list::HittableList,
     interval::Interval,
     material::AnyMaterial,
-    ray::Ray,
+    onb::Onb,
+    ray::{Differentials, Ray},
     sphere::Sphere,
+    transform::MotionTransform,
+    triangle::Triangle,
     vec3::{Point3, Vec3},
 };
 
@@ -24,12 +29,30 @@ pub enum AnyHittable {
     Sphere,
     HittableList,
     BvhNode,
+    Translate,
+    MotionTransform,
+    Triangle,
 }
 
 #[derive(Clone)]
 pub struct HitRecord<'a> {
     pub p: Point3,
     pub normal: Vec3,
+    // Partial derivatives of the position with respect to u and v, spanning the tangent plane,
+    // or zero until complete_tangents for surfaces without their own
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    // Partial derivatives of the outward normal with respect to u and v, zero for flat surfaces
+    pub dndu: Vec3,
+    pub dndv: Vec3,
+    // How the position and the texture coordinates change from one pixel to the next, found from
+    // the differentials of the ray, or zero for rays without them
+    pub dpdx: Vec3,
+    pub dpdy: Vec3,
+    pub dudx: f64,
+    pub dvdx: f64,
+    pub dudy: f64,
+    pub dvdy: f64,
     pub mat: &'a AnyMaterial,
     pub t: f64,
     pub u: f64,
@@ -57,6 +80,16 @@ impl<'a> HitRecord<'a> {
         Self {
             p,
             normal,
+            dpdu: Vec3::default(),
+            dpdv: Vec3::default(),
+            dndu: Vec3::default(),
+            dndv: Vec3::default(),
+            dpdx: Vec3::default(),
+            dpdy: Vec3::default(),
+            dudx: 0.,
+            dvdx: 0.,
+            dudy: 0.,
+            dvdy: 0.,
             mat,
             t,
             u,
@@ -64,4 +97,175 @@ impl<'a> HitRecord<'a> {
             front_face,
         }
     }
+
+    pub fn complete_tangents(&mut self) {
+        // Make up an arbitrary tangent frame where the surface doesn't provide its own, like at
+        // the poles of a sphere. Only the hit that gets shaded needs one, so shapes leave it out
+        // while they intersect the ray.
+        if self.dpdu.near_zero() || self.dpdv.near_zero() {
+            let uvw = Onb::build_from_w(self.normal);
+            self.dpdu = uvw.local(Vec3::new(1., 0., 0.));
+            self.dpdv = uvw.local(Vec3::new(0., 1., 0.));
+        }
+    }
+
+    pub fn compute_differentials(&mut self, r: Ray) {
+        // Intersect the neighboring rays with the tangent plane, and split their offsets from p
+        // into steps along dpdu and dpdv by least squares.
+        let Some(rd) = r.differentials() else {
+            return;
+        };
+        let n = self.normal;
+        let offset = |origin: Point3, direction: Vec3| {
+            let denom = n.dot(direction);
+            (denom.abs() > 1e-12).then(|| origin + (n.dot(self.p - origin) / denom) * direction)
+        };
+        let (Some(px), Some(py)) = (
+            offset(rd.rx_origin, rd.rx_direction),
+            offset(rd.ry_origin, rd.ry_direction),
+        ) else {
+            return;
+        };
+        self.dpdx = px - self.p;
+        self.dpdy = py - self.p;
+
+        let a00 = self.dpdu.dot(self.dpdu);
+        let a01 = self.dpdu.dot(self.dpdv);
+        let a11 = self.dpdv.dot(self.dpdv);
+        let det = a00 * a11 - a01 * a01;
+        if det.abs() < 1e-12 {
+            return;
+        }
+        let solve = |dp: Vec3| {
+            let (b0, b1) = (self.dpdu.dot(dp), self.dpdv.dot(dp));
+            ((a11 * b0 - a01 * b1) / det, (a00 * b1 - a01 * b0) / det)
+        };
+        (self.dudx, self.dvdx) = solve(self.dpdx);
+        (self.dudy, self.dvdy) = solve(self.dpdy);
+    }
+
+    // Width of the pixel's footprint in texture coordinates, or 0 for rays without differentials.
+    pub fn footprint(&self) -> f64 {
+        let x = (self.dudx * self.dudx + self.dvdx * self.dvdx).sqrt();
+        let y = (self.dudy * self.dudy + self.dvdy * self.dvdy).sqrt();
+        x.max(y)
+    }
+
+    // The footprint for lookups made before compute_differentials ran, like the opacity checks of
+    // shapes while they intersect the ray.
+    pub fn footprint_along(&self, r: Ray) -> f64 {
+        let mut rec = self.clone();
+        rec.compute_differentials(r);
+        rec.footprint()
+    }
+
+    // Differentials of a ray reflected in the mirror direction d - 2 (d·n) n, for the incoming
+    // unit direction d.
+    pub fn reflected_differentials(&self, r: Ray, scattered: Vec3) -> Option<Differentials> {
+        let d = r.direction().unit_vector();
+        let n = self.normal;
+        self.scattered_differentials(r, scattered, |dd, dn| {
+            dd - 2. * ((dd.dot(n) + d.dot(dn)) * n + d.dot(n) * dn)
+        })
+    }
+
+    // Differentials of a ray refracted in the direction eta d + (eta cos_i - cos_t) n, for the
+    // incoming unit direction d and the ratio eta of the indices of refraction.
+    pub fn refracted_differentials(
+        &self,
+        r: Ray,
+        scattered: Vec3,
+        eta: f64,
+    ) -> Option<Differentials> {
+        let d = r.direction().unit_vector();
+        let n = self.normal;
+        let cos_i = -d.dot(n);
+        let cos_t = (1. - eta * eta * (1. - cos_i * cos_i)).max(1e-6).sqrt();
+        let mu = eta * cos_i - cos_t;
+        self.scattered_differentials(r, scattered, |dd, dn| {
+            let dcos_i = -(dd.dot(n) + d.dot(dn));
+            let dmu = (eta - eta * eta * cos_i / cos_t) * dcos_i;
+            eta * dd + dmu * n + mu * dn
+        })
+    }
+
+    fn scattered_differentials(
+        &self,
+        r: Ray,
+        scattered: Vec3,
+        direction_change: impl Fn(Vec3, Vec3) -> Vec3,
+    ) -> Option<Differentials> {
+        // The neighboring rays leave from the neighboring points on the surface, turned by how the
+        // incoming direction and the normal change between them. direction_change maps those
+        // changes to the change of the scattered direction.
+        let rd = r.differentials()?;
+        let d = r.direction().unit_vector();
+        let side = if self.front_face { 1. } else { -1. };
+        let dndx = side * (self.dudx * self.dndu + self.dvdx * self.dndv);
+        let dndy = side * (self.dudy * self.dndu + self.dvdy * self.dndv);
+        let scattered = scattered.unit_vector();
+
+        Some(Differentials {
+            rx_origin: self.p + self.dpdx,
+            rx_direction: scattered + direction_change(rd.rx_direction.unit_vector() - d, dndx),
+            ry_origin: self.p + self.dpdy,
+            ry_direction: scattered + direction_change(rd.ry_direction.unit_vector() - d, dndy),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Translate {
+    object: Arc<AnyHittable>,
+    offset1: Vec3,
+    offset_vec: Vec3,
+    bbox: Aabb,
+}
+
+impl Translate {
+    pub fn new(object: impl Into<AnyHittable>, offset: Vec3) -> Self {
+        Self::moving(object, offset, offset)
+    }
+
+    pub fn moving(object: impl Into<AnyHittable>, offset1: Vec3, offset2: Vec3) -> Self {
+        let object = object.into();
+        let bbox = Aabb::from_aabs(
+            object.bounding_box() + offset1,
+            object.bounding_box() + offset2,
+        );
+
+        Self {
+            object: Arc::new(object),
+            offset1,
+            offset_vec: offset2 - offset1,
+            bbox,
+        }
+    }
+
+    pub fn offset(&self, time: f64) -> Vec3 {
+        self.offset1 + time * self.offset_vec
+    }
+}
+
+impl Hittable for Translate {
+    fn hit(&self, r: Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        // Move the ray backwards by the offset
+        let offset = self.offset(r.time());
+        let offset_r = Ray::new(r.origin() - offset, r.direction(), r.time()).with_differentials(
+            r.differentials()
+                .map(|d| d.transformed(|p| p - offset, |v| v)),
+        );
+
+        // Determine where (if any) an intersection occurs along the offset ray
+        let mut rec = self.object.hit(offset_r, ray_t)?;
+
+        // Move the intersection point forwards by the offset
+        rec.p += offset;
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
 }
@@ -1,3 +1,5 @@
+use std::ops::Add;
+
 use crate::rtweekend::INFINITY;
 
 #[derive(Clone, Copy)]
@@ -15,6 +17,10 @@ impl Interval {
         Self::new(a.min.min(b.min), a.max.max(b.max))
     }
 
+    pub fn size(self) -> f64 {
+        self.max - self.min
+    }
+
     pub fn surrounds(self, x: f64) -> bool {
         self.min < x && x < self.max
     }
@@ -33,10 +39,10 @@ impl Interval {
         }
     }
 
-    // pub fn expand(self, delta: f64) -> Self {
-    //     let padding = delta / 2.;
-    //     Self::new(self.min - padding, self.max + padding)
-    // }
+    pub fn expand(self, delta: f64) -> Self {
+        let padding = delta / 2.;
+        Self::new(self.min - padding, self.max + padding)
+    }
 }
 
 impl Default for Interval {
@@ -47,3 +53,11 @@ impl Default for Interval {
         }
     }
 }
+
+impl Add<f64> for Interval {
+    type Output = Self;
+
+    fn add(self, displacement: f64) -> Self::Output {
+        Self::new(self.min + displacement, self.max + displacement)
+    }
+}
@@ -0,0 +1,431 @@
+use rayon::prelude::*;
+
+use crate::{
+    rtweekend::random_double,
+    vec3::{Point3, Vec3},
+};
+
+// Wavelength in nanometers of the Fraunhofer d-line, at which lens prescriptions list the index of
+// refraction.
+pub const D_LINE_WAVELENGTH: f64 = 587.6;
+
+// Number of radial bands on the film with a precomputed exit pupil.
+const EXIT_PUPIL_BANDS: usize = 64;
+// Number of rays traced through the lens to bound the exit pupil of each band.
+const EXIT_PUPIL_SAMPLES: usize = 4096;
+
+#[derive(Clone, Copy)]
+struct LensElement {
+    // Radius of curvature of the spherical surface, 0 for the aperture stop
+    curvature_radius: f64,
+    // Distance along the optical axis to the next surface
+    thickness: f64,
+    // Index of refraction at the d-line behind the surface, 0 for the aperture stop
+    ior: f64,
+    // Radius of the clear aperture of the surface
+    aperture_radius: f64,
+    // Abbe number of the medium behind the surface, 0 for no dispersion
+    abbe: f64,
+}
+
+impl LensElement {
+    fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.
+    }
+
+    fn ior_at(&self, wavelength: f64) -> f64 {
+        // Index of refraction from a Cauchy fit through the d-line index and the Abbe number,
+        // which is defined by the indices at the F (486.1nm) and C (656.3nm) lines.
+        if self.abbe <= 0. || self.ior <= 1. {
+            return self.ior;
+        }
+
+        let dispersion = (self.ior - 1.) / self.abbe;
+        let b = dispersion / (1. / (486.1 * 486.1) - 1. / (656.3 * 656.3));
+        let a = self.ior - b / (D_LINE_WAVELENGTH * D_LINE_WAVELENGTH);
+        a + b / (wavelength * wavelength)
+    }
+}
+
+#[derive(Clone)]
+pub struct LensSystem {
+    // Lens surfaces from the front (scene side) to the back (film side), in meters
+    elements: Vec<LensElement>,
+}
+
+impl LensSystem {
+    pub fn parse(prescription: &str) -> Result<Self, String> {
+        // Each line lists one surface, from the front of the lens to the back, in millimeters:
+        // curvature radius, thickness, index of refraction, aperture diameter and optionally the
+        // Abbe number. The aperture stop has a radius and index of 0. '#' starts a comment.
+        let mut elements = vec![];
+
+        for (n, line) in prescription.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|err| format!("line {}: {err}", n + 1))?;
+            if values.len() != 4 && values.len() != 5 {
+                return Err(format!(
+                    "line {}: expected 4 or 5 values, found {}",
+                    n + 1,
+                    values.len()
+                ));
+            }
+
+            elements.push(LensElement {
+                curvature_radius: values[0] / 1000.,
+                thickness: values[1] / 1000.,
+                ior: values[2],
+                aperture_radius: values[3] / 2000.,
+                abbe: values.get(4).copied().unwrap_or(0.),
+            });
+        }
+
+        if elements.is_empty() {
+            return Err("lens prescription has no surfaces".to_string());
+        }
+
+        Ok(Self { elements })
+    }
+
+    pub fn focus(&self, focus_dist: f64, film_width: f64, film_height: f64) -> FocusedLens {
+        // Move the film so that the plane at focus_dist is sharp, then precompute the exit pupil.
+        let mut lens = FocusedLens {
+            elements: self.elements.clone(),
+            film_width,
+            film_height,
+            film_diagonal: (film_width * film_width + film_height * film_height).sqrt(),
+            exit_pupil_bounds: vec![],
+            center_transmission: 1.,
+        };
+
+        if let Some(film_dist) = lens.focus_thick_lens(focus_dist) {
+            lens.elements.last_mut().unwrap().thickness = film_dist;
+        } else {
+            eprintln!("WARNING: Lens can not focus at distance {focus_dist}.");
+        }
+
+        lens.exit_pupil_bounds = (0..EXIT_PUPIL_BANDS)
+            .into_par_iter()
+            .map(|i| {
+                let r0 = i as f64 / EXIT_PUPIL_BANDS as f64 * lens.film_diagonal / 2.;
+                let r1 = (i + 1) as f64 / EXIT_PUPIL_BANDS as f64 * lens.film_diagonal / 2.;
+                lens.bound_exit_pupil(r0, r1)
+            })
+            .collect();
+
+        // Measure how much of the exit pupil bounds lets light through at the film center.
+        let passed = (0..EXIT_PUPIL_SAMPLES)
+            .filter(|_| lens.sample_ray(0., 0., D_LINE_WAVELENGTH).is_some())
+            .count();
+        lens.center_transmission = (passed as f64 / EXIT_PUPIL_SAMPLES as f64).max(1e-3);
+
+        lens
+    }
+}
+
+// Lens system with the film placed at the focus distance. Coordinates are in camera space, with
+// the film centered on the origin and the lens in front of it along +z.
+pub struct FocusedLens {
+    elements: Vec<LensElement>,
+    film_width: f64,
+    film_height: f64,
+    film_diagonal: f64,
+    // Bounds [min x, min y, max x, max y] on the rear element of the rays leaving each radial band
+    // of the film along the +x axis that make it through the lens
+    exit_pupil_bounds: Vec<[f64; 4]>,
+    // Fraction of the rays sampled at the film center that make it through the lens
+    center_transmission: f64,
+}
+
+impl FocusedLens {
+    pub fn is_dispersive(&self) -> bool {
+        self.elements.iter().any(|element| element.abbe > 0.)
+    }
+
+    pub fn sample_ray(&self, s: f64, t: f64, wavelength: f64) -> Option<(Point3, Vec3, f64)> {
+        // Returns a random ray leaving the front of the lens for the film position s,t (fractions
+        // of the film size from its center, t pointing down the image) and its weight, or None if
+        // the lens blocks it. The image on the film is upside down, so the film position is
+        // mirrored.
+        let film_x = s * self.film_width;
+        let film_y = t * self.film_height;
+        let p_film = Point3::new(-film_x, film_y, 0.);
+
+        // Sample the exit pupil bounds of the film position's band, rotated around the axis.
+        let r = (film_x * film_x + film_y * film_y).sqrt();
+        let band = ((r / (self.film_diagonal / 2.) * EXIT_PUPIL_BANDS as f64) as usize)
+            .min(EXIT_PUPIL_BANDS - 1);
+        let bounds = self.exit_pupil_bounds[band];
+        let px = bounds[0] + random_double() * (bounds[2] - bounds[0]);
+        let py = bounds[1] + random_double() * (bounds[3] - bounds[1]);
+        let (sin_theta, cos_theta) = if r > 0. {
+            (p_film.y() / r, p_film.x() / r)
+        } else {
+            (0., 1.)
+        };
+        let p_rear = Point3::new(
+            cos_theta * px - sin_theta * py,
+            sin_theta * px + cos_theta * py,
+            self.rear_z(),
+        );
+
+        let direction = p_rear - p_film;
+        let (origin, out_direction) = self.trace_from_film(p_film, direction, wavelength)?;
+
+        // Light reaching the film falls off with the fourth power of the cosine of its angle, and
+        // with the area of the pupil the ray was drawn from. The weight is normalized so that the
+        // film center receives all of the light.
+        let cos_theta = direction.unit_vector().z();
+        let cos4_theta = (cos_theta * cos_theta) * (cos_theta * cos_theta);
+        let weight = cos4_theta * bounds_area(bounds)
+            / bounds_area(self.exit_pupil_bounds[0])
+            / self.center_transmission;
+
+        Some((origin, out_direction, weight))
+    }
+
+    fn front_z(&self) -> f64 {
+        self.elements.iter().map(|element| element.thickness).sum()
+    }
+
+    fn rear_z(&self) -> f64 {
+        self.elements.last().unwrap().thickness
+    }
+
+    fn rear_radius(&self) -> f64 {
+        self.elements.last().unwrap().aperture_radius
+    }
+
+    fn trace_from_film(
+        &self,
+        origin: Point3,
+        direction: Vec3,
+        wavelength: f64,
+    ) -> Option<(Point3, Vec3)> {
+        // Trace a ray from the film out of the front of the lens. Lens space mirrors camera space
+        // along z, so the film sits at z=0 and the surfaces are at negative z.
+        let mut o = flip_z(origin);
+        let mut d = flip_z(direction);
+        let mut element_z = 0.;
+
+        for i in (0..self.elements.len()).rev() {
+            let element = self.elements[i];
+            element_z -= element.thickness;
+
+            let (t, normal) = if element.is_stop() {
+                if d.z() >= 0. {
+                    return None;
+                }
+                ((element_z - o.z()) / d.z(), None)
+            } else {
+                let z_center = element_z + element.curvature_radius;
+                let (t, n) = intersect_spherical_element(element.curvature_radius, z_center, o, d)?;
+                (t, Some(n))
+            };
+
+            // Rays outside the clear aperture of the surface are blocked.
+            let p = o + t * d;
+            if p.x() * p.x() + p.y() * p.y() > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+            o = p;
+
+            if let Some(n) = normal {
+                let eta_i = element.ior_at(wavelength);
+                let eta_t = if i > 0 && self.elements[i - 1].ior != 0. {
+                    self.elements[i - 1].ior_at(wavelength)
+                } else {
+                    1.
+                };
+                d = refract(-d.unit_vector(), n, eta_i / eta_t)?;
+            }
+        }
+
+        Some((flip_z(o), flip_z(d)))
+    }
+
+    fn trace_from_scene(&self, origin: Point3, direction: Vec3) -> Option<(Point3, Vec3)> {
+        // Trace a ray from the scene through the front of the lens onto the film.
+        let mut o = flip_z(origin);
+        let mut d = flip_z(direction);
+        let mut element_z = -self.front_z();
+
+        for (i, element) in self.elements.iter().enumerate() {
+            let (t, normal) = if element.is_stop() {
+                ((element_z - o.z()) / d.z(), None)
+            } else {
+                let z_center = element_z + element.curvature_radius;
+                let (t, n) = intersect_spherical_element(element.curvature_radius, z_center, o, d)?;
+                (t, Some(n))
+            };
+
+            let p = o + t * d;
+            if p.x() * p.x() + p.y() * p.y() > element.aperture_radius * element.aperture_radius {
+                return None;
+            }
+            o = p;
+
+            if let Some(n) = normal {
+                let eta_i = if i == 0 || self.elements[i - 1].ior == 0. {
+                    1.
+                } else {
+                    self.elements[i - 1].ior
+                };
+                let eta_t = if element.ior != 0. { element.ior } else { 1. };
+                d = refract(-d.unit_vector(), n, eta_i / eta_t)?;
+            }
+
+            element_z += element.thickness;
+        }
+
+        Some((flip_z(o), flip_z(d)))
+    }
+
+    fn focus_thick_lens(&self, focus_dist: f64) -> Option<f64> {
+        // Find the cardinal points of the lens by tracing rays parallel to the axis from either
+        // side, then solve the thick lens equation for the film distance.
+        let x = 0.001 * self.film_diagonal;
+
+        let scene_origin = Point3::new(x, 0., self.front_z() + 1.);
+        let scene_direction = Vec3::new(0., 0., -1.);
+        let film_ray = self.trace_from_scene(scene_origin, scene_direction)?;
+        let (pz0, fz0) = cardinal_points((scene_origin, scene_direction), film_ray);
+
+        let film_origin = Point3::new(x, 0., self.rear_z() - 1.);
+        let film_direction = Vec3::new(0., 0., 1.);
+        let scene_ray = self.trace_from_film(film_origin, film_direction, D_LINE_WAVELENGTH)?;
+        let (pz1, _) = cardinal_points((film_origin, film_direction), scene_ray);
+
+        let f = fz0 - pz0;
+        let z = -focus_dist;
+        let c = (pz1 - z - pz0) * (pz1 - z - 4. * f - pz0);
+        if c <= 0. {
+            return None;
+        }
+        let delta = 0.5 * (pz1 - z + pz0 - c.sqrt());
+
+        Some(self.rear_z() + delta)
+    }
+
+    fn bound_exit_pupil(&self, r0: f64, r1: f64) -> [f64; 4] {
+        // Bound the points on the rear element that rays from film positions between r0 and r1
+        // along the x axis pass through on their way out of the lens.
+        let rear_bound = 1.5 * self.rear_radius();
+        let mut bounds = [f64::INFINITY, f64::INFINITY, -f64::INFINITY, -f64::INFINITY];
+        let grid = (EXIT_PUPIL_SAMPLES as f64).sqrt() as usize;
+
+        for i in 0..grid * grid {
+            let p_film = Point3::new(r0 + random_double() * (r1 - r0), 0., 0.);
+            let px =
+                -rear_bound + 2. * rear_bound * ((i % grid) as f64 + random_double()) / grid as f64;
+            let py =
+                -rear_bound + 2. * rear_bound * ((i / grid) as f64 + random_double()) / grid as f64;
+            let p_rear = Point3::new(px, py, self.rear_z());
+
+            let inside = px >= bounds[0] && px <= bounds[2] && py >= bounds[1] && py <= bounds[3];
+            if inside
+                || self
+                    .trace_from_film(p_film, p_rear - p_film, D_LINE_WAVELENGTH)
+                    .is_some()
+            {
+                bounds = [
+                    bounds[0].min(px),
+                    bounds[1].min(py),
+                    bounds[2].max(px),
+                    bounds[3].max(py),
+                ];
+            }
+        }
+
+        if bounds[0] > bounds[2] {
+            // No ray got through, fall back to the whole rear element.
+            return [-rear_bound, -rear_bound, rear_bound, rear_bound];
+        }
+
+        // Expand the bounds by the sample spacing to make up for rays between the samples.
+        let spacing = 2. * rear_bound / grid as f64;
+        [
+            bounds[0] - spacing,
+            bounds[1] - spacing,
+            bounds[2] + spacing,
+            bounds[3] + spacing,
+        ]
+    }
+}
+
+fn flip_z(v: Vec3) -> Vec3 {
+    Vec3::new(v.x(), v.y(), -v.z())
+}
+
+fn bounds_area(bounds: [f64; 4]) -> f64 {
+    (bounds[2] - bounds[0]) * (bounds[3] - bounds[1])
+}
+
+fn intersect_spherical_element(
+    radius: f64,
+    z_center: f64,
+    origin: Point3,
+    direction: Vec3,
+) -> Option<(f64, Vec3)> {
+    // Intersect the ray with the lens surface, picking the root on the side of the sphere the
+    // surface is on. Returns the distance and the normal facing the incoming ray.
+    let oc = origin - Point3::new(0., 0., z_center);
+    let a = direction.length_squared();
+    let half_b = oc.dot(direction);
+    let c = oc.length_squared() - radius * radius;
+
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let t0 = (-half_b - sqrtd) / a;
+    let t1 = (-half_b + sqrtd) / a;
+
+    let use_closer_t = (direction.z() > 0.) ^ (radius < 0.);
+    let t = if use_closer_t { t0 } else { t1 };
+    if t < 0. {
+        return None;
+    }
+
+    let n = (oc + t * direction).unit_vector();
+    let n = if n.dot(-direction) < 0. { -n } else { n };
+
+    Some((t, n))
+}
+
+fn refract(wi: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    // Refract the direction wi (pointing away from the surface) through the surface with normal n
+    // on the same side, or None on total internal reflection.
+    let cos_theta_i = n.dot(wi);
+    let sin2_theta_i = (1. - cos_theta_i * cos_theta_i).max(0.);
+    let sin2_theta_t = eta * eta * sin2_theta_i;
+    if sin2_theta_t >= 1. {
+        return None;
+    }
+    let cos_theta_t = (1. - sin2_theta_t).sqrt();
+
+    Some(eta * -wi + (eta * cos_theta_i - cos_theta_t) * n)
+}
+
+fn cardinal_points(r_in: (Point3, Vec3), r_out: (Point3, Vec3)) -> (f64, f64) {
+    // Returns the z positions of the principal plane and the focal point from a ray entering
+    // the lens parallel to the axis and the same ray leaving it.
+    let (in_origin, _) = r_in;
+    let (out_origin, out_direction) = r_out;
+
+    let tf = -out_origin.x() / out_direction.x();
+    let fz = -(out_origin + tf * out_direction).z();
+    let tp = (in_origin.x() - out_origin.x()) / out_direction.x();
+    let pz = -(out_origin + tp * out_direction).z();
+
+    (pz, fz)
+}
@@ -1,27 +1,58 @@
 #![warn(rust_2018_idioms)]
 
 use crate::{hittable_list::HittableList, sphere::Sphere, vec3::Point3};
+use animation::{Animation, Interpolation, ObjectTracks};
+use aperture::{Aperture, ApertureMask};
 use bvh::BvhNode;
-use camera::Camera;
+use camera::{Camera, PhysicalCamera, Projection, StereoLayout};
 use color::Color;
-use material::{Dielectric, Lambertian, Metal};
+use hittable::Translate;
+use image::ImageResult;
+use lens::LensSystem;
+use material::{
+    AlphaMode, AnyMaterial, BumpMapped, Coated, Conductor, Cutout, Dielectric, Lambertian, Metal,
+    MixMaterial, NormalMapped, Principled, RoughDielectric, Subsurface, ThinFilm, Velvet,
+};
+use medium::Medium;
+use mesh::Mesh;
+use pattern::{Pattern, PatternKind, UvTransform};
+use quat::Quat;
+use rtw_image::ColorSpace;
 use rtweekend::{random_double, random_double_min_max};
-use texture::{CheckerTexture, ImageTexture};
+use shutter::{ShutterCurve, ShutterMode, ShutterShape};
+use spectrum::Ior;
+use texture::{CheckerTexture, Filter, ImageTexture, SolidColor, WrapMode};
+use texture_cache::TextureCache;
+use transform::{MotionTransform, Transform};
 use vec3::Vec3;
 
 mod aabb;
+mod animation;
+mod aperture;
 mod bvh;
 mod camera;
 mod color;
 mod hittable;
 mod hittable_list;
 mod interval;
+mod lens;
 mod material;
+mod medium;
+mod mesh;
+mod microfacet;
+mod onb;
+mod pattern;
+mod quat;
 mod ray;
 mod rtw_image;
 mod rtweekend;
+mod shutter;
+mod spectrum;
 mod sphere;
 mod texture;
+mod texture_cache;
+mod transform;
+mod triangle;
 mod vec3;
 
 fn random_spheres() {
@@ -130,9 +161,34 @@ fn two_spheres() {
     cam.render(&world);
 }
 
+fn load<T>(filename: &str, loader: impl FnOnce(&str) -> ImageResult<T>) -> Option<T> {
+    // Report images that can't be loaded, leaving it to the caller to skip the scene.
+    loader(filename)
+        .map_err(|error| eprintln!("ERROR: Could not load image file {filename}: {error}."))
+        .ok()
+}
+
+fn report_decode_errors(textures: &TextureCache) {
+    for (path, error) in textures.take_errors() {
+        let path = path.display();
+        eprintln!("ERROR: Could not decode image file {path}: {error}. It was rendered black.");
+    }
+}
+
 fn earth() {
-    let earth_texture =
-        ImageTexture::new(concat!(env!("CARGO_MANIFEST_DIR"), "/images/earthmap.jpg"));
+    // Load the texture through a cache of our own, with a smaller budget than the shared one.
+    let textures = TextureCache::new(64 << 20);
+    let earth_image = textures.image(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/images/earthmap.jpg"),
+        ColorSpace::Srgb,
+    );
+    let earth_texture = match earth_image {
+        Ok(image) => ImageTexture::from_image(image),
+        Err(error) => {
+            eprintln!("ERROR: Could not load the earth texture: {error}.");
+            return;
+        }
+    };
     let earth_surface = Lambertian::new(earth_texture);
     let globe = Sphere::new(Point3::new(0., 0., 0.), 2., earth_surface);
 
@@ -143,14 +199,1298 @@ fn earth() {
     cam.samples_per_pixel = 100;
     cam.max_depth = 50;
 
-    cam.vfov = 20.;
-    cam.lookfrom = Point3::new(0., 0., 12.);
-    cam.lookat = Point3::new(0., 0., 0.);
+    cam.vfov = 20.;
+    cam.lookfrom = Point3::new(0., 0., 12.);
+    cam.lookat = Point3::new(0., 0., 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.render(&HittableList::from_hittable(globe));
+    report_decode_errors(&textures);
+}
+
+fn animated_spheres() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+    world.add(Translate::new(
+        Sphere::new(Point3::new(0., 0., 0.), 1., Dielectric::new(1.5)),
+        Vec3::new(0., 1., 0.),
+    ));
+
+    let mut animation = Animation::new(world);
+
+    let mut bounce = ObjectTracks::new();
+    bounce
+        .translation
+        .add(0., Vec3::new(-4., 1., 0.), Interpolation::Bezier);
+    bounce
+        .translation
+        .add(12., Vec3::new(-2., 3., 1.), Interpolation::Bezier);
+    bounce
+        .translation
+        .add(24., Vec3::new(0., 1., 2.), Interpolation::Linear);
+    animation.add(
+        Sphere::new(
+            Point3::new(0., 0., 0.),
+            1.,
+            Metal::new(Color::new(0.7, 0.6, 0.5), 0.),
+        ),
+        bounce,
+    );
+
+    // A globe turning a full circle about its tilted axis, squashing as it lands and stretching
+    // as it springs back up.
+    let mut globe = ObjectTracks::new();
+    globe
+        .translation
+        .add(0., Vec3::new(1.5, 1., -3.5), Interpolation::Bezier);
+    globe
+        .translation
+        .add(12., Vec3::new(1.5, 0.6, -3.5), Interpolation::Bezier);
+    globe
+        .translation
+        .add(24., Vec3::new(1.5, 1.2, -3.5), Interpolation::Linear);
+    let tilt = Quat::from_axis_angle(Vec3::new(0., 0., 1.), 23.5);
+    for key in 0..=6 {
+        // Keys a sixth of a turn apart, as rotations blend along the shortest arc.
+        let (frame, degrees) = (4. * key as f64, 60. * key as f64);
+        let spin = Quat::from_axis_angle(Vec3::new(0., 1., 0.), degrees);
+        globe
+            .rotation
+            .add(frame, tilt * spin, Interpolation::Bezier);
+    }
+    globe
+        .scale
+        .add(0., Vec3::new(1., 1., 1.), Interpolation::Bezier);
+    globe
+        .scale
+        .add(12., Vec3::new(1.2, 0.6, 1.2), Interpolation::Bezier);
+    globe
+        .scale
+        .add(24., Vec3::new(0.9, 1.2, 0.9), Interpolation::Linear);
+    let Some(earth_texture) = load(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/images/earthmap.jpg"),
+        ImageTexture::new,
+    ) else {
+        return;
+    };
+    animation.add(
+        Sphere::new(Point3::new(0., 0., 0.), 1., Lambertian::new(earth_texture)),
+        globe,
+    );
+
+    animation
+        .lookfrom
+        .add(0., Point3::new(13., 2., 3.), Interpolation::Bezier);
+    animation
+        .lookfrom
+        .add(24., Point3::new(3., 3., 13.), Interpolation::Bezier);
+    animation.vfov.add(0., 20., Interpolation::Linear);
+    animation.vfov.add(24., 30., Interpolation::Linear);
+    animation.shutter_close.add(0., 0.5, Interpolation::Linear);
+    animation.shutter_close.add(24., 0.9, Interpolation::Linear);
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.lookat = Point3::new(0., 0., 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    if let Err(err) = animation.render(&mut cam, 0..=24, "frame_") {
+        eprintln!("ERROR: Could not write frame: {err}");
+    }
+}
+
+fn motion_blur() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // A spinning globe, rotating a quarter turn around its tilted axis while the shutter is open.
+    let Some(earth_texture) = load(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/images/earthmap.jpg"),
+        ImageTexture::new,
+    ) else {
+        return;
+    };
+    let globe = Sphere::new(Point3::new(0., 0., 0.), 1., Lambertian::new(earth_texture));
+    let tilt = Quat::from_axis_angle(Vec3::new(0., 0., 1.), 23.5);
+    let spin = Quat::from_axis_angle(Vec3::new(0., 1., 0.), 90.);
+    world.add(MotionTransform::new(
+        globe,
+        Transform::new(Vec3::new(0., 1., 0.), tilt, Vec3::new(1., 1., 1.)),
+        Transform::new(Vec3::new(0., 1., 0.), tilt * spin, Vec3::new(1., 1., 1.)),
+    ));
+
+    // A metal ellipsoid that flies to the right while stretching along its path.
+    let blob = Sphere::new(
+        Point3::new(0., 0., 0.),
+        1.,
+        Metal::new(Color::new(0.7, 0.6, 0.5), 0.),
+    );
+    world.add(MotionTransform::new(
+        blob,
+        Transform::new(
+            Vec3::new(-3., 0.5, 1.),
+            Quat::identity(),
+            Vec3::new(0.5, 0.5, 0.5),
+        ),
+        Transform::new(
+            Vec3::new(-2., 0.5, 1.),
+            Quat::identity(),
+            Vec3::new(1., 0.4, 0.4),
+        ),
+    ));
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 30.;
+    cam.lookfrom = Point3::new(0., 2., 10.);
+    cam.lookat = Point3::new(-1., 0.75, 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.shutter_shape = ShutterShape::Triangle;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn rolling_shutter() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // A sphere racing past the camera, skewed by the top-to-bottom readout of the sensor.
+    world.add(Sphere::moving(
+        Point3::new(-6., 1., 0.),
+        Point3::new(6., 1., 0.),
+        1.,
+        Lambertian::from_color(Color::new(0.8, 0.3, 0.1)),
+    ));
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 30.;
+    cam.lookfrom = Point3::new(0., 2., 12.);
+    cam.lookat = Point3::new(0., 1., 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    // A short exposure with a soft opening and a hard close, read out over most of the motion.
+    cam.shutter_open = 0.;
+    cam.shutter_close = 0.1;
+    cam.shutter_shape = ShutterShape::Custom(ShutterCurve::new(&[0.2, 0.6, 1., 1., 1.]));
+    cam.shutter_mode = ShutterMode::Rolling { readout: 0.5 };
+
+    cam.render(&world);
+}
+
+fn ring_of_spheres() -> HittableList {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // A ring of spheres all around the camera.
+    for k in 0..12 {
+        let angle = k as f64 * 30_f64.to_radians();
+        let center = Point3::new(6. * angle.cos(), 1., 6. * angle.sin());
+        let albedo = Color::new(0.5 + 0.4 * angle.cos(), 0.5, 0.5 + 0.4 * angle.sin());
+        if k % 3 == 0 {
+            world.add(Sphere::new(center, 1., Metal::new(albedo, 0.1)));
+        } else {
+            world.add(Sphere::new(center, 1., Lambertian::from_color(albedo)));
+        }
+    }
+
+    HittableList::from_hittable(BvhNode::from_list(world))
+}
+
+fn projections(projection: Projection) {
+    let world = ring_of_spheres();
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.projection = projection;
+    cam.lookfrom = Point3::new(0., 1.5, 0.);
+    cam.lookat = Point3::new(0., 1., -6.);
+    cam.vup = Vec3::new(0., 1., 0.);
+    cam.vfov = 60.;
+
+    cam.defocus_angle = 0.5;
+    cam.focus_dist = 6.;
+
+    match projection {
+        Projection::Orthographic => {
+            // Parallel rays start on the camera plane, so view the ring from outside and above.
+            cam.lookfrom = Point3::new(0., 8., 14.);
+            cam.lookat = Point3::new(0., 1., 0.);
+            cam.vfov = 40.;
+            cam.focus_dist = (cam.lookfrom - cam.lookat).length();
+        }
+        Projection::FisheyeEquidistant | Projection::FisheyeEquisolid => cam.vfov = 180.,
+        Projection::Equirectangular => cam.aspect_ratio = 2.,
+        Projection::Perspective => {}
+    }
+
+    cam.render(&world);
+}
+
+fn stereo(projection: Projection, stereo: StereoLayout) {
+    let world = ring_of_spheres();
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.projection = projection;
+    cam.vfov = 60.;
+    cam.lookfrom = Point3::new(0., 1.5, 0.);
+    cam.lookat = Point3::new(0., 1., -6.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+    cam.focus_dist = 6.;
+
+    // Exaggerated eye separation, converging on the ring of spheres.
+    cam.stereo = stereo;
+    cam.interocular_dist = 0.3;
+    cam.convergence_dist = 6.;
+
+    if let Projection::Equirectangular = projection {
+        cam.aspect_ratio = 2.;
+    }
+
+    cam.render(&world);
+}
+
+fn bokeh(aperture: Aperture) {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // A sharp subject in front of a field of small shiny spheres catching the sky, with a dark
+    // backdrop to make their out-of-focus highlights stand out.
+    world.add(Sphere::new(
+        Point3::new(0., 0., -1040.),
+        1000.,
+        Lambertian::from_color(Color::new(0.02, 0.02, 0.02)),
+    ));
+    world.add(Sphere::new(
+        Point3::new(0., 1., 0.),
+        1.,
+        Lambertian::from_color(Color::new(0.4, 0.2, 0.1)),
+    ));
+    for a in -10..10 {
+        for b in 0..6 {
+            let center = Point3::new(
+                2. * a as f64 + random_double(),
+                0.2 + 3. * random_double(),
+                -10. - 4. * b as f64,
+            );
+            world.add(Sphere::new(
+                center,
+                0.1,
+                Metal::new(Color::new(1., 1., 1.), 0.),
+            ));
+        }
+    }
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 40.;
+    cam.lookfrom = Point3::new(0., 1.5, 6.);
+    cam.lookat = Point3::new(0., 1., 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 3.;
+    cam.focus_dist = 6.;
+    cam.aperture = aperture;
+    cam.cat_eye = 0.5;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn physical_camera() {
+    let world = ring_of_spheres();
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.lookfrom = Point3::new(0., 1.5, 0.);
+    cam.lookat = Point3::new(0., 1., -6.);
+    cam.vup = Vec3::new(0., 1., 0.);
+    cam.focus_dist = 6.;
+
+    // A fast 35mm lens wide open on an APS-C sensor, at a shutter speed that leaves the image about
+    // two thirds of a stop brighter than the "sunny 16" exposure.
+    let mut physical = PhysicalCamera::new(35., 1.4);
+    physical.sensor_width = 23.6;
+    physical.shutter_speed = 1. / 8000.;
+    physical.iso = 100.;
+    cam.physical = Some(physical);
+
+    cam.render(&world);
+}
+
+fn tilt_shift() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // Rows of spheres receding into the distance.
+    for a in -3..=3 {
+        for b in 0..10 {
+            let center = Point3::new(2. * a as f64, 0.5, -3. * b as f64);
+            let albedo = Color::new(0.2 + 0.1 * (a + 3) as f64, 0.3, 0.9 - 0.08 * b as f64);
+            world.add(Sphere::new(center, 0.5, Lambertian::from_color(albedo)));
+        }
+    }
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    // The camera looks straight ahead and shifts the view down to the ground, so that vertical
+    // lines stay parallel.
+    cam.lookfrom = Point3::new(0., 3., 8.);
+    cam.lookat = Point3::new(0., 3., 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+    cam.shift_y = -0.35;
+    cam.focus_dist = 20.;
+
+    // Tilting the lens forward pivots the plane of focus around a line on the ground below the
+    // camera, sloping up gently through the focus distance.
+    let mut physical = PhysicalCamera::new(45., 2.8);
+    physical.shutter_speed = 1. / 3200.;
+    physical.lens_tilt = 0.86;
+    cam.physical = Some(physical);
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+// Double Gauss 50mm f/2 (US patent 2,673,491), with Abbe numbers added for chromatic aberration.
+const DOUBLE_GAUSS_50MM: &str = "
+# radius  thickness  ior    aperture  abbe
+  29.475  3.76       1.67   25.2      47.
+  84.83   0.12       1.     25.2
+  19.275  4.025      1.67   23        47.
+  40.77   3.275      1.699  23        30.
+  12.75   5.705      1.     18
+  0       4.5        0      17.1
+ -14.495  1.18       1.603  17        38.
+  40.77   6.065      1.658  20        57.
+ -20.385  0.19       1.     20
+  437.065 3.22       1.717  20        48.
+ -39.73   0          1.     20
+";
+
+fn realistic_lens() {
+    let world = ring_of_spheres();
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.lookfrom = Point3::new(0., 1.5, 0.);
+    cam.lookat = Point3::new(0., 1., -6.);
+    cam.vup = Vec3::new(0., 1., 0.);
+    cam.focus_dist = 6.;
+
+    match LensSystem::parse(DOUBLE_GAUSS_50MM) {
+        Ok(lens) => cam.lens = Some(lens),
+        Err(err) => eprintln!("ERROR: Could not parse lens prescription: {err}"),
+    }
+
+    cam.render(&world);
+}
+
+fn metals() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // Polished, satin and brushed versions of each metal, from front to back.
+    for (row, roughness) in [0.05, 0.3, 0.6].into_iter().enumerate() {
+        let z = -2.5 * row as f64;
+        let metals = [
+            Conductor::gold(roughness),
+            Conductor::copper(roughness),
+            Conductor::aluminium(roughness),
+            Conductor::silver(roughness),
+            Conductor::anisotropic(
+                Color::new(1.657, 0.880, 0.521),
+                Color::new(9.224, 6.270, 4.837),
+                0.05,
+                roughness,
+            ),
+        ];
+        for (col, metal) in metals.into_iter().enumerate() {
+            let x = 2.5 * col as f64 - 5.;
+            world.add(Sphere::new(Point3::new(x, 1., z), 1., metal));
+        }
+    }
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 35.;
+    cam.lookfrom = Point3::new(0., 5., 12.);
+    cam.lookat = Point3::new(0., 0.5, -2.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn frosted_glass() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // Glass getting rougher from left to right, in front of colored spheres seen through it.
+    for (i, roughness) in [0., 0.1, 0.25, 0.5].into_iter().enumerate() {
+        let x = 2.5 * i as f64 - 3.75;
+        world.add(Sphere::new(
+            Point3::new(x, 1., 0.),
+            1.,
+            RoughDielectric::new(1.5, roughness),
+        ));
+        world.add(Sphere::new(
+            Point3::new(x, 0.5, -3.),
+            0.5,
+            Lambertian::from_color(Color::new(0.8, 0.2 + 0.2 * i as f64, 0.1)),
+        ));
+    }
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 35.;
+    cam.lookfrom = Point3::new(0., 2., 10.);
+    cam.lookat = Point3::new(0., 1., 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn tinted_glass() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // Bigger spheres look darker, as light travels further through the absorbing glass.
+    let ruby = Medium::from_color(Color::new(0.9, 0.1, 0.2), 1.);
+    for (i, radius) in [0.4, 0.7, 1.].into_iter().enumerate() {
+        world.add(Sphere::new(
+            Point3::new(-3.5 + 1.6 * i as f64, radius, 0.),
+            radius,
+            Dielectric::absorbing(1.76, ruby),
+        ));
+    }
+
+    // The biggest one has a bubble inside, as a glass to air interface surrounded by ruby.
+    world.add(Sphere::new(
+        Point3::new(-0.3, 1., 0.),
+        0.5,
+        Dielectric::new(1. / 1.76).with_outside(ruby),
+    ));
+
+    // An emerald with a bubble too, frosted like the emerald's surface.
+    let emerald = Medium::from_color(Color::new(0.2, 0.8, 0.4), 1.);
+    world.add(Sphere::new(
+        Point3::new(1.6, 1., 0.),
+        1.,
+        RoughDielectric::absorbing(1.57, 0.2, emerald),
+    ));
+    world.add(Sphere::new(
+        Point3::new(1.6, 1., 0.),
+        0.5,
+        RoughDielectric::new(1. / 1.57, 0.2).with_outside(emerald),
+    ));
+
+    // A hollow blue glass shell: the inner sphere faces inwards, so the thin wall is all
+    // that absorbs.
+    let blue = Medium::from_color(Color::new(0.2, 0.4, 0.9), 0.2);
+    world.add(Sphere::new(
+        Point3::new(3.8, 1., 0.),
+        1.,
+        Dielectric::absorbing(1.5, blue),
+    ));
+    world.add(Sphere::new(
+        Point3::new(3.8, 1., 0.),
+        -0.9,
+        Dielectric::absorbing(1.5, blue),
+    ));
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 40.;
+    cam.lookfrom = Point3::new(0., 3., 11.);
+    cam.lookat = Point3::new(0., 0.8, 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn dispersion() {
+    let mut world = HittableList::new();
+
+    let checker = CheckerTexture::from_solid(
+        0.32,
+        Color::new(0.05, 0.05, 0.05),
+        Color::new(0.9, 0.9, 0.9),
+    );
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // From left to right the glasses split light into its colors more and more.
+    let water = Ior::Cauchy {
+        a: 1.3199,
+        b: 0.006_53,
+    };
+    for (i, ior) in [water, Ior::bk7(), Ior::sf11(), Ior::diamond()]
+        .into_iter()
+        .enumerate()
+    {
+        world.add(Sphere::new(
+            Point3::new(2.5 * i as f64 - 3.75, 1., 0.),
+            1.,
+            Dielectric::new(ior),
+        ));
+    }
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 35.;
+    cam.lookfrom = Point3::new(0., 2., 10.);
+    cam.lookat = Point3::new(0., 1., 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.spectral = true;
+    cam.wavelengths_per_sample = 4;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn principled() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // Back row: gold colored metal getting rougher from left to right.
+    for i in 0..5 {
+        let metal = Principled::from_color(Color::new(1., 0.71, 0.29))
+            .with_metallic(1.)
+            .with_roughness(0.25 * i as f64);
+        world.add(Sphere::new(
+            Point3::new(2.2 * i as f64 - 4.4, 1., -2.5),
+            1.,
+            metal,
+        ));
+    }
+
+    // Front row: glossy plastic, clearcoated paint, velvet, glass and wax.
+    let plastic = Principled::from_color(Color::new(0.1, 0.2, 0.5)).with_roughness(0.2);
+    let paint = Principled::from_color(Color::new(0.6, 0.05, 0.05))
+        .with_metallic(0.3)
+        .with_roughness(0.6)
+        .with_specular(0.8)
+        .with_clearcoat(1.)
+        .with_clearcoat_roughness(0.05);
+    let velvet = Principled::from_color(Color::new(0.3, 0.05, 0.3))
+        .with_roughness(1.)
+        .with_sheen(1.)
+        .with_sheen_tint(1.);
+    let glass = Principled::from_color(Color::new(0.9, 1., 0.9))
+        .with_roughness(0.05)
+        .with_transmission(1.)
+        .with_ior(Ior::bk7());
+
+    // Wax, with its roughness driven by a texture.
+    let roughness =
+        CheckerTexture::from_solid(0.2, Color::new(0.1, 0.1, 0.1), Color::new(0.8, 0.8, 0.8));
+    let wax = Principled::new(SolidColor::new(Color::new(0.9, 0.8, 0.6)))
+        .with_roughness(roughness)
+        .with_subsurface(1.);
+
+    for (i, material) in [plastic, paint, velvet, glass, wax].into_iter().enumerate() {
+        world.add(Sphere::new(
+            Point3::new(2.2 * i as f64 - 4.4, 0.8, 0.5),
+            0.8,
+            material,
+        ));
+    }
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 35.;
+    cam.lookfrom = Point3::new(0., 4., 12.);
+    cam.lookat = Point3::new(0., 0.8, -1.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn layered() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    let red = Lambertian::from_color(Color::new(0.7, 0.1, 0.1));
+    let amber = Medium::from_color(Color::new(0.9, 0.6, 0.2), 0.05);
+    let materials: [AnyMaterial; 5] = [
+        // Glossy lacquer over a red diffuse base.
+        Coated::new(red.clone(), 1.5, 0.).into(),
+        // Satin lacquer over the same base.
+        Coated::new(red, 1.5, 0.3).into(),
+        // Thin clear coat over rough copper.
+        Coated::new(Conductor::copper(0.4), 1.5, 0.05).into(),
+        // Thick amber varnish over white.
+        Coated::tinted(
+            Lambertian::from_color(Color::new(0.9, 0.9, 0.9)),
+            1.5,
+            0.,
+            amber,
+            0.1,
+        )
+        .into(),
+        // Rusty steel, mixing diffuse rust into polished metal by a pattern.
+        MixMaterial::new(
+            Metal::new(Color::new(0.7, 0.7, 0.75), 0.05),
+            Lambertian::from_color(Color::new(0.4, 0.15, 0.05)),
+            CheckerTexture::from_solid(0.15, Color::new(0.2, 0.2, 0.2), Color::new(0.9, 0.9, 0.9)),
+        )
+        .into(),
+    ];
+
+    for (i, material) in materials.into_iter().enumerate() {
+        world.add(Sphere::new(
+            Point3::new(2.2 * i as f64 - 4.4, 1., 0.),
+            1.,
+            material,
+        ));
+    }
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 35.;
+    cam.lookfrom = Point3::new(0., 3., 12.);
+    cam.lookat = Point3::new(0., 1., 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn subsurface() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // Candle wax, jade, marble and skin, with mean free paths in scene units on spheres of
+    // radius 1. Red light travels furthest through skin.
+    let materials = [
+        Subsurface::new(
+            Color::new(0.95, 0.85, 0.6),
+            Color::new(0.3, 0.2, 0.1),
+            1.45,
+            0.3,
+        ),
+        Subsurface::new(
+            Color::new(0.3, 0.75, 0.4),
+            Color::new(0.3, 0.6, 0.4),
+            1.66,
+            0.1,
+        ),
+        Subsurface::new(
+            Color::new(0.93, 0.92, 0.9),
+            Color::new(0.1, 0.1, 0.1),
+            1.49,
+            0.05,
+        ),
+        Subsurface::new(
+            Color::new(0.85, 0.55, 0.45),
+            Color::new(0.12, 0.05, 0.03),
+            1.4,
+            0.4,
+        ),
+    ];
+
+    for (i, material) in materials.into_iter().enumerate() {
+        world.add(Sphere::new(
+            Point3::new(2.5 * i as f64 - 3.75, 1., 0.),
+            1.,
+            material,
+        ));
+    }
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    // Random walks inside the spheres take many bounces.
+    cam.max_depth = 500;
+
+    cam.vfov = 35.;
+    cam.lookfrom = Point3::new(0., 2., 10.);
+    cam.lookat = Point3::new(0., 1., 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn iridescence_and_sheen() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // Back row: soap bubbles of increasing film thickness, and oil on water.
+    for (i, thickness) in [250., 400., 550., 700.].into_iter().enumerate() {
+        world.add(Sphere::new(
+            Point3::new(2.2 * i as f64 - 4.4, 1.2, -2.5),
+            1.,
+            ThinFilm::new(thickness, 1.33, 1.),
+        ));
+    }
+    world.add(Sphere::new(
+        Point3::new(4.4, 1.2, -2.5),
+        1.,
+        ThinFilm::new(350., 1.47, 1.33),
+    ));
+
+    // Front row: velvet of increasing roughness, and velvet sheen over a dark diffuse base.
+    for (i, roughness) in [0.3, 0.6, 1.].into_iter().enumerate() {
+        world.add(Sphere::new(
+            Point3::new(2.2 * i as f64 - 3.3, 0.8, 0.5),
+            0.8,
+            Velvet::from_color(Color::new(0.8, 0.2, 0.4), roughness),
+        ));
+    }
+    world.add(Sphere::new(
+        Point3::new(3.3, 0.8, 0.5),
+        0.8,
+        MixMaterial::new(
+            Lambertian::from_color(Color::new(0.1, 0.02, 0.2)),
+            Velvet::from_color(Color::new(0.6, 0.3, 0.9), 0.7),
+            0.5,
+        ),
+    ));
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 35.;
+    cam.lookfrom = Point3::new(0., 4., 12.);
+    cam.lookat = Point3::new(0., 0.8, -1.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn surface_detail() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // A globe with the continents raised by bump mapping its own brightness, read as raw data.
+    let Some(earth_texture) = load(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/images/earthmap.jpg"),
+        ImageTexture::new,
+    ) else {
+        return;
+    };
+    let Some(earth_height) = load(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/images/earthmap.jpg"),
+        ImageTexture::linear,
+    ) else {
+        return;
+    };
+    world.add(Sphere::new(
+        Point3::new(-2.2, 1.5, 0.),
+        1.5,
+        BumpMapped::new(Lambertian::new(earth_texture), earth_height, 0.02),
+    ));
+
+    // Hammered metal, from a normal map of facets tilted alternately left and right.
+    let facets =
+        CheckerTexture::from_solid(0.15, Color::new(0.8, 0.5, 0.9), Color::new(0.2, 0.5, 0.9));
+    world.add(Sphere::new(
+        Point3::new(2.2, 1.5, 0.),
+        1.5,
+        NormalMapped::new(Conductor::copper(0.1), facets, 0.5),
+    ));
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 30.;
+    cam.lookfrom = Point3::new(0., 3., 12.);
+    cam.lookat = Point3::new(0., 1.5, 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn cutouts() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    let leaf = load(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/images/leaf.png"),
+        ImageTexture::new,
+    );
+    let Some(leaf) = leaf else {
+        return;
+    };
+
+    // A leaf wrapped around a sphere, with the rest of the sphere cut away.
+    world.add(Sphere::new(
+        Point3::new(-2.2, 1.5, 0.),
+        1.5,
+        Cutout::new(
+            Lambertian::new(leaf.clone()),
+            leaf.clone(),
+            AlphaMode::Threshold(0.5),
+        ),
+    ));
+
+    // The same leaf as a decal on a red ball, from a slightly larger sphere with soft edges.
+    world.add(Sphere::new(
+        Point3::new(2.2, 1.5, 0.),
+        1.5,
+        Lambertian::from_color(Color::new(0.7, 0.1, 0.1)),
+    ));
+    world.add(Sphere::new(
+        Point3::new(2.2, 1.5, 0.),
+        1.501,
+        Cutout::new(Lambertian::new(leaf.clone()), leaf, AlphaMode::Stochastic),
+    ));
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 30.;
+    cam.lookfrom = Point3::new(0., 3., 12.);
+    cam.lookat = Point3::new(0., 1.5, 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn displacement() {
+    let mut world = HittableList::new();
+
+    let checker =
+        CheckerTexture::from_solid(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(checker),
+    ));
+
+    // A globe with its continents pushed out, changing its silhouette unlike bump mapping.
+    let Some(earth_texture) = load(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/images/earthmap.jpg"),
+        ImageTexture::new,
+    ) else {
+        return;
+    };
+    let Some(earth_height) = load(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/images/earthmap.jpg"),
+        ImageTexture::linear,
+    ) else {
+        return;
+    };
+    world.add(
+        Mesh::sphere(Point3::new(-2., 1.7, 0.), 1.5, 0.01)
+            .displace(earth_height, 0.15)
+            .build(Lambertian::new(earth_texture)),
+    );
+
+    // A square slab tessellated finely enough to raise a grid of tiles out of it.
+    let slab = Mesh::new(
+        vec![
+            Point3::new(0.5, 0.01, -1.5),
+            Point3::new(3.5, 0.01, -1.5),
+            Point3::new(3.5, 0.01, 1.5),
+            Point3::new(0.5, 0.01, 1.5),
+        ],
+        vec![Vec3::new(0., 1., 0.); 4],
+        vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)],
+        vec![[0, 2, 1], [0, 3, 2]],
+    );
+    let tiles = CheckerTexture::from_solid(0.5, Color::new(0., 0., 0.), Color::new(1., 1., 1.));
+    world.add(
+        slab.tessellate(0.03)
+            .displace(tiles, 0.3)
+            .build(Metal::new(Color::new(0.8, 0.6, 0.3), 0.3)),
+    );
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 30.;
+    cam.lookfrom = Point3::new(0., 4., 12.);
+    cam.lookat = Point3::new(0.5, 1., 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn texture_filtering() {
+    let mut world = HittableList::new();
+
+    // A flat rectangle from corner along the u and v edges, showing the given range of texture
+    // coordinates.
+    let panel = |corner: Point3, u_edge: Vec3, v_edge: Vec3, (u0, v0), (u1, v1)| {
+        Mesh::new(
+            vec![
+                corner,
+                corner + u_edge,
+                corner + u_edge + v_edge,
+                corner + v_edge,
+            ],
+            vec![u_edge.cross(v_edge).unit_vector(); 4],
+            vec![(u0, v0), (u1, v0), (u1, v1), (u0, v1)],
+            vec![[0, 1, 2], [0, 2, 3]],
+        )
+    };
+
+    // The earth's map tiled across the ground, repeated on the left and mirrored on the right.
+    let Some(earth) = load(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/images/earthmap.jpg"),
+        ImageTexture::new,
+    ) else {
+        return;
+    };
+    for (x, wrap) in [(-8., WrapMode::Repeat), (0., WrapMode::Mirror)] {
+        world.add(
+            panel(
+                Point3::new(x, 0., 4.),
+                Vec3::new(8., 0., 0.),
+                Vec3::new(0., 0., -8.),
+                (-1., -1.),
+                (2., 2.),
+            )
+            .build(Lambertian::new(
+                earth.clone().with_filter(Filter::Trilinear).with_wrap(wrap),
+            )),
+        );
+    }
+
+    // Italy blown up to a few pixels across, sampled with each of the filters.
+    let filters = [Filter::Nearest, Filter::Bilinear, Filter::Bicubic];
+    for (i, filter) in filters.into_iter().enumerate() {
+        world.add(
+            panel(
+                Point3::new(-3.3 + 2.2 * i as f64, 0.5, 0.),
+                Vec3::new(2., 0., 0.),
+                Vec3::new(0., 2., 0.),
+                (0.53, 0.72),
+                (0.545, 0.75),
+            )
+            .build(Lambertian::new(earth.clone().with_filter(filter))),
+        );
+    }
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 30.;
+    cam.lookfrom = Point3::new(0., 3., 12.);
+    cam.lookat = Point3::new(0., 1., 0.);
+    cam.vup = Vec3::new(0., 1., 0.);
+
+    cam.defocus_angle = 0.;
+
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
+}
+
+fn patterns() {
+    let mut world = HittableList::new();
+
+    let solid = |r: f64, g: f64, b: f64| SolidColor::new(Color::new(r, g, b));
+
+    // A world space checker of finer checkers and plain squares.
+    let fine =
+        CheckerTexture::from_solid(0.25, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        Lambertian::new(CheckerTexture::new(1., fine, solid(0.5, 0.5, 0.5))),
+    ));
+
+    // Patterns in texture coordinates, sized to roughly square cells around the equator.
+    let stripes = Pattern::new(
+        PatternKind::Stripes { width: 0.5 },
+        solid(0.1, 0.2, 0.6),
+        solid(0.9, 0.8, 0.2),
+    );
+    let textures = [
+        // Checker with striped squares, each square holding one pair of stripes
+        UvTransform::new(Pattern::new(
+            PatternKind::Checker,
+            solid(0.7, 0.1, 0.1),
+            stripes.clone(),
+        ))
+        .with_scale(16., 8.),
+        // Stripes turned diagonally
+        UvTransform::new(stripes)
+            .with_scale(12., 6.)
+            .with_rotation(30.),
+        UvTransform::new(Pattern::new(
+            PatternKind::Grid { line_width: 0.1 },
+            solid(0.9, 0.9, 0.9),
+            solid(0.1, 0.1, 0.1),
+        ))
+        .with_scale(16., 8.),
+        // Gradient from the top down, turning v into u
+        UvTransform::new(Pattern::new(
+            PatternKind::Gradient,
+            solid(0.9, 0.5, 0.1),
+            solid(0.3, 0.1, 0.5),
+        ))
+        .with_rotation(90.)
+        .with_offset(1., 0.),
+        UvTransform::new(Pattern::new(
+            PatternKind::Bricks { mortar_width: 0.05 },
+            solid(0.6, 0.2, 0.1),
+            solid(0.7, 0.7, 0.7),
+        ))
+        .with_scale(8., 8.),
+        UvTransform::new(Pattern::new(
+            PatternKind::Tiles { grout_width: 0.1 },
+            solid(0.1, 0.5, 0.5),
+            solid(0.9, 0.9, 0.9),
+        ))
+        .with_scale(16., 8.),
+    ];
+    for (i, texture) in textures.into_iter().enumerate() {
+        let center = Point3::new(-2.2 + 2.2 * (i % 3) as f64, 0.9 + 2. * (i / 3) as f64, 0.);
+        world.add(Sphere::new(center, 0.9, Lambertian::new(texture)));
+    }
+
+    let mut cam = Camera::new();
+
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+
+    cam.vfov = 30.;
+    cam.lookfrom = Point3::new(0., 3., 12.);
+    cam.lookat = Point3::new(0., 1.8, 0.);
     cam.vup = Vec3::new(0., 1., 0.);
 
     cam.defocus_angle = 0.;
 
-    cam.render(&HittableList::from_hittable(globe))
+    cam.render(&HittableList::from_hittable(BvhNode::from_list(world)));
 }
 
 fn main() {
@@ -158,6 +1498,47 @@ fn main() {
         1 => random_spheres(),
         2 => two_spheres(),
         3 => earth(),
+        4 => animated_spheres(),
+        5 => motion_blur(),
+        6 => rolling_shutter(),
+        7 => projections(Projection::Orthographic),
+        8 => projections(Projection::FisheyeEquidistant),
+        9 => projections(Projection::FisheyeEquisolid),
+        10 => projections(Projection::Equirectangular),
+        11 => stereo(Projection::Perspective, StereoLayout::SideBySide),
+        12 => stereo(Projection::Equirectangular, StereoLayout::TopBottom),
+        13 => bokeh(Aperture::Polygon {
+            blades: 6,
+            rotation: 15.,
+        }),
+        14 => bokeh(Aperture::Annular { inner_radius: 0.6 }),
+        15 => {
+            if let Some(mask) = load(
+                concat!(env!("CARGO_MANIFEST_DIR"), "/images/earthmap.jpg"),
+                ApertureMask::new,
+            ) {
+                bokeh(Aperture::Mask(mask))
+            }
+        }
+        16 => physical_camera(),
+        17 => tilt_shift(),
+        18 => realistic_lens(),
+        19 => metals(),
+        20 => frosted_glass(),
+        21 => tinted_glass(),
+        22 => dispersion(),
+        23 => principled(),
+        24 => layered(),
+        25 => subsurface(),
+        26 => iridescence_and_sheen(),
+        27 => surface_detail(),
+        28 => cutouts(),
+        29 => displacement(),
+        30 => texture_filtering(),
+        31 => patterns(),
         _ => {}
     }
+
+    // Images are only decoded once they're sampled, so errors in them show up after rendering.
+    report_decode_errors(TextureCache::global());
 }
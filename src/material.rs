@@ -1,17 +1,29 @@
+use std::sync::Arc;
+
 use enum_dispatch::enum_dispatch;
 
 use crate::{
     color::Color,
     hittable::HitRecord,
+    medium::Medium,
+    microfacet::{fresnel_conductor, fresnel_dielectric, fresnel_thin_film, TrowbridgeReitz},
+    onb::Onb,
     ray::Ray,
-    rtweekend::random_double,
+    rtweekend::{random_double, PI},
+    spectrum::Ior,
     texture::{AnyTexture, SolidColor, Texture},
-    vec3::Vec3,
+    vec3::{Point3, Vec3},
 };
 
 #[enum_dispatch]
 pub trait Material {
     fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)>;
+
+    // Whether the ray stops at the hit. Rays pass through cut out parts of surfaces as if they
+    // weren't there. Shapes ask this before the record's differentials are computed.
+    fn is_opaque(&self, _r: Ray, _rec: &HitRecord<'_>) -> bool {
+        true
+    }
 }
 
 #[enum_dispatch(Material)]
@@ -20,6 +32,27 @@ pub enum AnyMaterial {
     Lambertian,
     Metal,
     Dielectric,
+    Conductor,
+    RoughDielectric,
+    Principled,
+    MixMaterial,
+    Coated,
+    Subsurface,
+    ThinFilm,
+    Velvet,
+    NormalMapped,
+    BumpMapped,
+    Cutout,
+}
+
+fn medium_after_refraction(front_face: bool, inside: Medium, outside: Medium) -> Medium {
+    // Refracting in through the front face enters the inside medium, refracting out enters the
+    // outside one.
+    if front_face {
+        inside
+    } else {
+        outside
+    }
 }
 
 #[derive(Clone)]
@@ -46,8 +79,10 @@ impl Material for Lambertian {
             scatter_direction = rec.normal;
         }
 
-        let scattered = Ray::new(rec.p, scatter_direction, r_in.time());
-        let attenuation = self.albedo.value(rec.u, rec.v, rec.p);
+        let scattered = r_in.scattered(rec.p, scatter_direction);
+        let attenuation = self
+            .albedo
+            .filtered_value(rec.u, rec.v, rec.p, rec.footprint());
 
         Some((attenuation, scattered))
     }
@@ -71,11 +106,9 @@ impl Metal {
 impl Material for Metal {
     fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
         let reflected = r_in.direction().unit_vector().reflect(rec.normal);
-        let scattered = Ray::new(
-            rec.p,
-            reflected + self.fuzz * Vec3::random_unit_vector(),
-            r_in.time(),
-        );
+        let scattered = r_in.scattered(rec.p, reflected + self.fuzz * Vec3::random_unit_vector());
+        let scattered =
+            scattered.with_differentials(rec.reflected_differentials(r_in, scattered.direction()));
         let attenuation = self.albedo;
 
         if scattered.direction().dot(rec.normal) > 0. {
@@ -89,16 +122,33 @@ impl Material for Metal {
 #[derive(Clone, Copy)]
 pub struct Dielectric {
     // Index of Refraction
-    ir: f64,
+    ir: Ior,
+    // What fills the inside, absorbing light on its way through
+    medium: Medium,
+    // What surrounds the object, for objects nested inside others like bubbles in glass or a
+    // liquid in a glass. Empty space by default.
+    outside: Medium,
 }
 
 impl Dielectric {
-    pub fn new(index_of_refraction: f64) -> Self {
+    pub fn new(index_of_refraction: impl Into<Ior>) -> Self {
+        Self::absorbing(index_of_refraction, Medium::default())
+    }
+
+    pub fn absorbing(index_of_refraction: impl Into<Ior>, medium: Medium) -> Self {
         Self {
-            ir: index_of_refraction,
+            ir: index_of_refraction.into(),
+            medium,
+            outside: Medium::default(),
         }
     }
 
+    // Index of refraction and medium of the inside are relative to the outside medium.
+    pub fn with_outside(mut self, medium: Medium) -> Self {
+        self.outside = medium;
+        self
+    }
+
     fn reflectance(&self, cosine: f64, ref_idx: f64) -> f64 {
         // Use Schlick's approximation for reflectance.
         let mut r0 = (1. - ref_idx) / (1. + ref_idx);
@@ -110,11 +160,8 @@ impl Dielectric {
 impl Material for Dielectric {
     fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
         let attenuation = Color::new(1., 1., 1.);
-        let refraction_ratio = if rec.front_face {
-            1. / self.ir
-        } else {
-            self.ir
-        };
+        let ir = self.ir.at(r_in.wavelength());
+        let refraction_ratio = if rec.front_face { 1. / ir } else { ir };
 
         let unit_direction = r_in.direction().unit_vector();
         let cos_theta = (-unit_direction).dot(rec.normal).min(1.0);
@@ -122,15 +169,890 @@ impl Material for Dielectric {
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let direction =
+        let (direction, medium, differentials) =
             if cannot_refract || self.reflectance(cos_theta, refraction_ratio) > random_double() {
-                unit_direction.reflect(rec.normal)
+                let direction = unit_direction.reflect(rec.normal);
+                (
+                    direction,
+                    r_in.medium(),
+                    rec.reflected_differentials(r_in, direction),
+                )
             } else {
-                unit_direction.refract(rec.normal, refraction_ratio)
+                let direction = unit_direction.refract(rec.normal, refraction_ratio);
+                (
+                    direction,
+                    medium_after_refraction(rec.front_face, self.medium, self.outside),
+                    rec.refracted_differentials(r_in, direction, refraction_ratio),
+                )
             };
 
-        let scattered = Ray::new(rec.p, direction, r_in.time());
+        let scattered = r_in
+            .scattered(rec.p, direction)
+            .with_medium(medium)
+            .with_differentials(differentials);
 
         Some((attenuation, scattered))
     }
 }
+
+#[derive(Clone, Copy)]
+pub struct Conductor {
+    // Complex index of refraction eta + ik per color channel
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Self::anisotropic(eta, k, roughness, roughness)
+    }
+
+    pub fn anisotropic(eta: Color, k: Color, roughness_u: f64, roughness_v: f64) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::new(roughness_u, roughness_v),
+        }
+    }
+
+    pub fn gold(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f64) -> Self {
+        Self::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    pub fn silver(roughness: f64) -> Self {
+        Self::new(
+            Color::new(0.155, 0.117, 0.138),
+            Color::new(4.828, 3.122, 2.147),
+            roughness,
+        )
+    }
+
+    fn fresnel(&self, cos_theta: f64) -> Color {
+        Color::new(
+            fresnel_conductor(cos_theta, self.eta.x(), self.k.x()),
+            fresnel_conductor(cos_theta, self.eta.y(), self.k.y()),
+            fresnel_conductor(cos_theta, self.eta.z(), self.k.z()),
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
+        // Reflect off a microfacet normal sampled from the normals visible from the incoming
+        // direction, in the local frame of the surface, with x along the tangent so that
+        // anisotropic roughness follows the direction of increasing u.
+        let uvw = Onb::build_from_w_and_u(rec.normal, rec.dpdu);
+        let wo = uvw.to_local(-r_in.direction().unit_vector());
+        if wo.z() <= 0. {
+            return None;
+        }
+
+        let wm = self.distribution.sample_visible_normal(wo);
+        let wi = (-wo).reflect(wm);
+        if wi.z() <= 0. {
+            return None;
+        }
+
+        // With visible normal sampling, the reflectance divided by the sampling density reduces
+        // to the Fresnel term times the share of the reflected light that isn't masked.
+        let attenuation =
+            self.fresnel(wo.dot(wm)) * (self.distribution.g(wo, wi) / self.distribution.g1(wo));
+        let scattered = r_in.scattered(rec.p, uvw.local(wi));
+
+        Some((attenuation, scattered))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RoughDielectric {
+    // Index of Refraction
+    ir: Ior,
+    distribution: TrowbridgeReitz,
+    medium: Medium,
+    // What surrounds the object, as for Dielectric
+    outside: Medium,
+}
+
+impl RoughDielectric {
+    pub fn new(index_of_refraction: impl Into<Ior>, roughness: f64) -> Self {
+        Self::absorbing(index_of_refraction, roughness, Medium::default())
+    }
+
+    pub fn absorbing(index_of_refraction: impl Into<Ior>, roughness: f64, medium: Medium) -> Self {
+        Self {
+            ir: index_of_refraction.into(),
+            distribution: TrowbridgeReitz::new(roughness, roughness),
+            medium,
+            outside: Medium::default(),
+        }
+    }
+
+    pub fn with_outside(mut self, medium: Medium) -> Self {
+        self.outside = medium;
+        self
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
+        // Walter et al. microfacet BTDF: sample a visible microfacet normal, then reflect or
+        // refract through it with the probability given by the exact Fresnel equations.
+        let ir = self.ir.at(r_in.wavelength());
+        let eta = if rec.front_face { ir } else { 1. / ir };
+
+        let uvw = Onb::build_from_w_and_u(rec.normal, rec.dpdu);
+        let wo = uvw.to_local(-r_in.direction().unit_vector());
+        if wo.z() <= 0. {
+            return None;
+        }
+
+        let wm = self.distribution.sample_visible_normal(wo);
+        let reflectance = fresnel_dielectric(wo.dot(wm), eta);
+
+        // Total internal reflection has a reflectance of 1, so it always reflects.
+        let (wi, medium) = if random_double() < reflectance {
+            let wi = (-wo).reflect(wm);
+            if wi.z() <= 0. {
+                return None;
+            }
+            (wi, r_in.medium())
+        } else {
+            let wi = (-wo).refract(wm, 1. / eta);
+            if wi.z() >= 0. {
+                return None;
+            }
+            (
+                wi,
+                medium_after_refraction(rec.front_face, self.medium, self.outside),
+            )
+        };
+
+        // Choosing between reflection and refraction by the Fresnel term cancels it out of the
+        // weight, leaving the share of the scattered light that isn't masked.
+        let g = self.distribution.g(wo, wi) / self.distribution.g1(wo);
+        let attenuation = Color::new(g, g, g);
+        let scattered = r_in.scattered(rec.p, uvw.local(wi)).with_medium(medium);
+
+        Some((attenuation, scattered))
+    }
+}
+
+// Disney style "uber" material layering a clearcoat over a blend of metal, glass and a diffuse
+// base with sheen and subsurface approximations. Scalar parameters read the average of their
+// texture's channels.
+#[derive(Clone)]
+pub struct Principled {
+    base_color: Arc<AnyTexture>,
+    // Blend from dielectric (0) to metal (1)
+    metallic: Arc<AnyTexture>,
+    roughness: Arc<AnyTexture>,
+    // Strength of the dielectric specular reflection, 0.5 for the Fresnel reflectance of the IOR
+    specular: Arc<AnyTexture>,
+    // Retroreflective fabric-like rim, and how much it takes on the base color
+    sheen: Arc<AnyTexture>,
+    sheen_tint: Arc<AnyTexture>,
+    // Strength and roughness of a colorless glossy coat over everything else
+    clearcoat: Arc<AnyTexture>,
+    clearcoat_roughness: Arc<AnyTexture>,
+    // Blend from an opaque (0) to a fully refractive (1) dielectric
+    transmission: Arc<AnyTexture>,
+    // Blend from diffuse (0) to a flattened subsurface-like look (1)
+    subsurface: Arc<AnyTexture>,
+    // Index of refraction of the dielectric base and transmission
+    ior: Ior,
+}
+
+impl Principled {
+    pub fn new(base_color: impl Into<AnyTexture>) -> Self {
+        let constant = |value: f64| Arc::new(AnyTexture::from(value));
+        Self {
+            base_color: Arc::new(base_color.into()),
+            metallic: constant(0.),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            sheen: constant(0.),
+            sheen_tint: constant(0.5),
+            clearcoat: constant(0.),
+            clearcoat_roughness: constant(0.1),
+            transmission: constant(0.),
+            subsurface: constant(0.),
+            ior: Ior::Constant(1.5),
+        }
+    }
+
+    pub fn from_color(base_color: Color) -> Self {
+        Self::new(SolidColor::new(base_color))
+    }
+
+    pub fn with_metallic(self, metallic: impl Into<AnyTexture>) -> Self {
+        Self {
+            metallic: Arc::new(metallic.into()),
+            ..self
+        }
+    }
+
+    pub fn with_roughness(self, roughness: impl Into<AnyTexture>) -> Self {
+        Self {
+            roughness: Arc::new(roughness.into()),
+            ..self
+        }
+    }
+
+    pub fn with_specular(self, specular: impl Into<AnyTexture>) -> Self {
+        Self {
+            specular: Arc::new(specular.into()),
+            ..self
+        }
+    }
+
+    pub fn with_sheen(self, sheen: impl Into<AnyTexture>) -> Self {
+        Self {
+            sheen: Arc::new(sheen.into()),
+            ..self
+        }
+    }
+
+    pub fn with_sheen_tint(self, sheen_tint: impl Into<AnyTexture>) -> Self {
+        Self {
+            sheen_tint: Arc::new(sheen_tint.into()),
+            ..self
+        }
+    }
+
+    pub fn with_clearcoat(self, clearcoat: impl Into<AnyTexture>) -> Self {
+        Self {
+            clearcoat: Arc::new(clearcoat.into()),
+            ..self
+        }
+    }
+
+    pub fn with_clearcoat_roughness(self, clearcoat_roughness: impl Into<AnyTexture>) -> Self {
+        Self {
+            clearcoat_roughness: Arc::new(clearcoat_roughness.into()),
+            ..self
+        }
+    }
+
+    pub fn with_transmission(self, transmission: impl Into<AnyTexture>) -> Self {
+        Self {
+            transmission: Arc::new(transmission.into()),
+            ..self
+        }
+    }
+
+    pub fn with_subsurface(self, subsurface: impl Into<AnyTexture>) -> Self {
+        Self {
+            subsurface: Arc::new(subsurface.into()),
+            ..self
+        }
+    }
+
+    pub fn with_ior(self, ior: impl Into<Ior>) -> Self {
+        Self {
+            ior: ior.into(),
+            ..self
+        }
+    }
+}
+
+fn scalar(texture: &AnyTexture, u: f64, v: f64, p: Point3, footprint: f64) -> f64 {
+    let c = texture.filtered_value(u, v, p, footprint);
+    (c.x() + c.y() + c.z()) / 3.
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1. - cos_theta).clamp(0., 1.).powi(5)
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
+        // Each lobe is picked with the probability of the share of light it takes, so the weight
+        // of the sampled direction only carries the lobe's own color and masking.
+        let (u, v, p, footprint) = (rec.u, rec.v, rec.p, rec.footprint());
+        let base_color = self.base_color.filtered_value(u, v, p, footprint);
+        let roughness = scalar(&self.roughness, u, v, p, footprint);
+
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.direction().unit_vector());
+        if wo.z() <= 0. {
+            return None;
+        }
+
+        let reflect = |distribution: TrowbridgeReitz, wm: Vec3| {
+            let wi = (-wo).reflect(wm);
+            (wi.z() > 0.).then(|| (wi, distribution.g(wo, wi) / distribution.g1(wo)))
+        };
+        let scattered = |wi: Vec3| r_in.scattered(rec.p, uvw.local(wi));
+
+        // Clearcoat, reflecting by the Fresnel reflectance of a polyurethane-like coat.
+        let clearcoat = scalar(&self.clearcoat, u, v, p, footprint);
+        if clearcoat > 0. {
+            let cr = scalar(&self.clearcoat_roughness, u, v, p, footprint);
+            let distribution = TrowbridgeReitz::new(cr, cr);
+            let wm = distribution.sample_visible_normal(wo);
+            if random_double() < clearcoat * fresnel_dielectric(wo.dot(wm), 1.5) {
+                let (wi, g) = reflect(distribution, wm)?;
+                return Some((Color::new(g, g, g), scattered(wi)));
+            }
+        }
+
+        let distribution = TrowbridgeReitz::new(roughness, roughness);
+        let wm = distribution.sample_visible_normal(wo);
+
+        // Metal, tinted by the base color with Schlick's approximation.
+        if random_double() < scalar(&self.metallic, u, v, p, footprint) {
+            let (wi, g) = reflect(distribution, wm)?;
+            let fresnel =
+                base_color + (Color::new(1., 1., 1.) - base_color) * schlick_weight(wo.dot(wm));
+            return Some((g * fresnel, scattered(wi)));
+        }
+
+        // Dielectric specular reflection.
+        let ir = self.ior.at(r_in.wavelength());
+        let eta = if rec.front_face { ir } else { 1. / ir };
+        let specular = 2. * scalar(&self.specular, u, v, p, footprint);
+        if random_double() < (specular * fresnel_dielectric(wo.dot(wm), eta)).min(1.) {
+            let (wi, g) = reflect(distribution, wm)?;
+            return Some((Color::new(g, g, g), scattered(wi)));
+        }
+
+        // Transmission, tinting the refracted light by the base color.
+        if random_double() < scalar(&self.transmission, u, v, p, footprint) {
+            let wi = (-wo).refract(wm, 1. / eta);
+            if wi.z() >= 0. {
+                return None;
+            }
+            let g = distribution.g(wo, wi) / distribution.g1(wo);
+            return Some((g * base_color, scattered(wi)));
+        }
+
+        // Diffuse, sampled by the cosine of the angle to the normal so that the weight is the
+        // BRDF times pi.
+        let mut wi = Vec3::new(0., 0., 1.) + Vec3::random_unit_vector();
+        if wi.near_zero() {
+            wi = Vec3::new(0., 0., 1.);
+        }
+        let wi = wi.unit_vector();
+        let wh = (wo + wi).unit_vector();
+        let cos_d = wi.dot(wh);
+        let (fl, fv) = (schlick_weight(wi.z()), schlick_weight(wo.z()));
+
+        // Burley's retroreflective diffuse, and Hanrahan-Krueger inspired flattening for subsurface.
+        let fd90 = 0.5 + 2. * roughness * cos_d * cos_d;
+        let diffuse = (1. + (fd90 - 1.) * fl) * (1. + (fd90 - 1.) * fv);
+        let fss90 = roughness * cos_d * cos_d;
+        let fss = (1. + (fss90 - 1.) * fl) * (1. + (fss90 - 1.) * fv);
+        let ss = 1.25 * (fss * (1. / (wi.z() + wo.z()) - 0.5) + 0.5);
+        let subsurface = scalar(&self.subsurface, u, v, p, footprint);
+        let diffuse = diffuse + (ss - diffuse) * subsurface;
+
+        // Sheen, tinted towards the hue of the base color.
+        let luminance = 0.3 * base_color.x() + 0.6 * base_color.y() + 0.1 * base_color.z();
+        let tint = if luminance > 0. {
+            base_color / luminance
+        } else {
+            Color::new(1., 1., 1.)
+        };
+        let sheen_tint = scalar(&self.sheen_tint, u, v, p, footprint);
+        let sheen_color = Color::new(1., 1., 1.) + (tint - Color::new(1., 1., 1.)) * sheen_tint;
+        let sheen = scalar(&self.sheen, u, v, p, footprint) * schlick_weight(cos_d) * PI;
+
+        Some((diffuse * base_color + sheen * sheen_color, scattered(wi)))
+    }
+}
+
+// Blend of two materials, picking the second one with the probability given by the weight.
+#[derive(Clone)]
+pub struct MixMaterial {
+    first: Arc<AnyMaterial>,
+    second: Arc<AnyMaterial>,
+    weight: Arc<AnyTexture>,
+}
+
+impl MixMaterial {
+    pub fn new(
+        first: impl Into<AnyMaterial>,
+        second: impl Into<AnyMaterial>,
+        weight: impl Into<AnyTexture>,
+    ) -> Self {
+        Self {
+            first: Arc::new(first.into()),
+            second: Arc::new(second.into()),
+            weight: Arc::new(weight.into()),
+        }
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
+        if random_double() < scalar(&self.weight, rec.u, rec.v, rec.p, rec.footprint()) {
+            self.second.scatter(r_in, rec)
+        } else {
+            self.first.scatter(r_in, rec)
+        }
+    }
+
+    fn is_opaque(&self, r: Ray, rec: &HitRecord<'_>) -> bool {
+        // Picked the same way as for scattering, so the surface is there as often as the blend
+        // of the two coverages.
+        let weight = scalar(&self.weight, rec.u, rec.v, rec.p, rec.footprint_along(r));
+        if random_double() < weight {
+            self.second.is_opaque(r, rec)
+        } else {
+            self.first.is_opaque(r, rec)
+        }
+    }
+}
+
+// Number of bounces inside the coating before a path is given up.
+const COATING_MAX_BOUNCES: i32 = 32;
+
+// Dielectric coating, like a clear or tinted lacquer, over any base material. Light is followed
+// on a random walk bouncing between the coating's surface and the base, so that whatever isn't
+// reflected by one is left for the other and no energy is gained or lost.
+#[derive(Clone)]
+pub struct Coated {
+    base: Arc<AnyMaterial>,
+    // Index of Refraction of the coating
+    ir: Ior,
+    distribution: TrowbridgeReitz,
+    // What the coating is made of, and how thick it is
+    medium: Medium,
+    thickness: f64,
+}
+
+impl Coated {
+    pub fn new(
+        base: impl Into<AnyMaterial>,
+        index_of_refraction: impl Into<Ior>,
+        roughness: f64,
+    ) -> Self {
+        Self::tinted(base, index_of_refraction, roughness, Medium::default(), 0.)
+    }
+
+    pub fn tinted(
+        base: impl Into<AnyMaterial>,
+        index_of_refraction: impl Into<Ior>,
+        roughness: f64,
+        medium: Medium,
+        thickness: f64,
+    ) -> Self {
+        Self {
+            base: Arc::new(base.into()),
+            ir: index_of_refraction.into(),
+            distribution: TrowbridgeReitz::new(roughness, roughness),
+            medium,
+            thickness,
+        }
+    }
+
+    fn interface(&self, wo: Vec3, eta: f64) -> Option<(Vec3, f64)> {
+        // Scatters off the rough surface of the coating from the side wo lies on, which is taken
+        // as +z, with eta the ratio of the index on the other side to this side. Returns the new
+        // direction and its masking weight.
+        let wm = self.distribution.sample_visible_normal(wo);
+        let wi = if random_double() < fresnel_dielectric(wo.dot(wm), eta) {
+            let wi = (-wo).reflect(wm);
+            if wi.z() <= 0. {
+                return None;
+            }
+            wi
+        } else {
+            let wi = (-wo).refract(wm, 1. / eta);
+            if wi.z() >= 0. {
+                return None;
+            }
+            wi
+        };
+        Some((wi, self.distribution.g(wo, wi) / self.distribution.g1(wo)))
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.direction().unit_vector());
+        if wo.z() <= 0. {
+            return None;
+        }
+        let ir = self.ir.at(r_in.wavelength());
+
+        // Reflect off the top of the coating, or refract into it.
+        let (mut w, g) = self.interface(wo, ir)?;
+        if w.z() > 0. {
+            return Some((Color::new(g, g, g), r_in.scattered(rec.p, uvw.local(w))));
+        }
+        let mut attenuation = Color::new(g, g, g);
+
+        // Seen from inside the coating the surface faces down, so mirror directions in z to
+        // scatter off it.
+        let mirror = |w: Vec3| Vec3::new(w.x(), w.y(), -w.z());
+
+        for _ in 0..COATING_MAX_BOUNCES {
+            // Down through the coating to the base, and back up after scattering off it.
+            attenuation = attenuation * self.medium.transmittance(self.thickness / w.z().abs());
+            let (base_attenuation, scattered) = self
+                .base
+                .scatter(r_in.scattered(rec.p, uvw.local(w)), rec)?;
+            w = uvw.to_local(scattered.direction().unit_vector());
+            if w.z() <= 0. {
+                return None;
+            }
+            attenuation =
+                attenuation * base_attenuation * self.medium.transmittance(self.thickness / w.z());
+
+            // Out of the coating, or reflected back down towards the base.
+            let (wi, g) = self.interface(mirror(-w), 1. / ir)?;
+            attenuation *= g;
+            w = mirror(wi);
+            if w.z() > 0. {
+                return Some((attenuation, r_in.scattered(rec.p, uvw.local(w))));
+            }
+        }
+
+        None
+    }
+
+    fn is_opaque(&self, r: Ray, rec: &HitRecord<'_>) -> bool {
+        self.base.is_opaque(r, rec)
+    }
+}
+
+// Translucent material like wax, jade, marble or skin. Light refracts through a rough dielectric
+// surface into a scattering medium, and takes a random walk through it until it leaves again.
+#[derive(Clone, Copy)]
+pub struct Subsurface {
+    surface: RoughDielectric,
+}
+
+impl Subsurface {
+    pub fn new(
+        albedo: Color,
+        mean_free_path: Color,
+        index_of_refraction: impl Into<Ior>,
+        roughness: f64,
+    ) -> Self {
+        Self {
+            surface: RoughDielectric::absorbing(
+                index_of_refraction,
+                roughness,
+                Medium::from_albedo(albedo, mean_free_path),
+            ),
+        }
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
+        self.surface.scatter(r_in, rec)
+    }
+}
+
+// Smooth surface with a thin transparent film on it, like a soap bubble or oil on water. Light
+// reflected off the top and the bottom of the film interferes, coloring the reflection by the
+// film's thickness.
+#[derive(Clone, Copy)]
+pub struct ThinFilm {
+    // Film thickness in nanometers
+    thickness: f64,
+    film_ir: f64,
+    // Index of Refraction of what's under the film, 1 for a soap bubble
+    substrate_ir: Ior,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, film_ior: f64, substrate_ior: impl Into<Ior>) -> Self {
+        Self {
+            thickness,
+            film_ir: film_ior,
+            substrate_ir: substrate_ior.into(),
+        }
+    }
+}
+
+impl Material for ThinFilm {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
+        let substrate_ir = self.substrate_ir.at(r_in.wavelength());
+        let (eta_incident, eta_substrate) = if rec.front_face {
+            (1., substrate_ir)
+        } else {
+            (substrate_ir, 1.)
+        };
+
+        let unit_direction = r_in.direction().unit_vector();
+        let cos_theta = (-unit_direction).dot(rec.normal).min(1.0);
+
+        // Outside of spectral mode, the color channels are represented by a wavelength each.
+        let reflectance = |wavelength| {
+            fresnel_thin_film(
+                cos_theta,
+                eta_incident,
+                self.film_ir,
+                eta_substrate,
+                self.thickness,
+                wavelength,
+            )
+        };
+        let reflectance = match r_in.wavelength() {
+            Some(lambda) => {
+                let r = reflectance(lambda);
+                Color::new(r, r, r)
+            }
+            None => Color::new(reflectance(610.), reflectance(550.), reflectance(465.)),
+        };
+
+        // Reflect or refract with the probability of the average reflectance, weighting by the
+        // reflectance or transmittance of each channel relative to it.
+        let probability = (reflectance.x() + reflectance.y() + reflectance.z()) / 3.;
+        if random_double() < probability {
+            let direction = unit_direction.reflect(rec.normal);
+            let scattered = r_in
+                .scattered(rec.p, direction)
+                .with_differentials(rec.reflected_differentials(r_in, direction));
+            Some((reflectance / probability, scattered))
+        } else {
+            let eta = eta_incident / eta_substrate;
+            let direction = unit_direction.refract(rec.normal, eta);
+            let scattered = r_in
+                .scattered(rec.p, direction)
+                .with_differentials(rec.refracted_differentials(r_in, direction, eta));
+            let transmittance = Color::new(1., 1., 1.) - reflectance;
+            Some((transmittance / (1. - probability), scattered))
+        }
+    }
+}
+
+// Retroreflective sheen of fabrics like velvet, reflecting the most light at grazing angles. Uses
+// the "Charlie" distribution of fibers from Estevez and Kulla, "Production Friendly Microfacet
+// Sheen BRDF", with Ashikhmin's visibility term.
+#[derive(Clone)]
+pub struct Velvet {
+    albedo: AnyTexture,
+    alpha: f64,
+}
+
+impl Velvet {
+    pub fn new(a: impl Into<AnyTexture>, roughness: f64) -> Self {
+        Self {
+            albedo: a.into(),
+            alpha: (roughness * roughness).clamp(1e-3, 1.),
+        }
+    }
+
+    pub fn from_color(a: Color, roughness: f64) -> Self {
+        Self::new(SolidColor::new(a), roughness)
+    }
+}
+
+impl Material for Velvet {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.direction().unit_vector());
+        if wo.z() <= 0. {
+            return None;
+        }
+
+        // Sample by the cosine of the angle to the normal, so that the weight is the BRDF times pi.
+        let mut wi = Vec3::new(0., 0., 1.) + Vec3::random_unit_vector();
+        if wi.near_zero() {
+            wi = Vec3::new(0., 0., 1.);
+        }
+        let wi = wi.unit_vector();
+
+        let wh = (wo + wi).unit_vector();
+        let sin_theta_h = (1. - wh.z() * wh.z()).max(0.).sqrt();
+        let inv_alpha = 1. / self.alpha;
+        let d = (2. + inv_alpha) * sin_theta_h.powf(inv_alpha) / (2. * PI);
+        let v = 1. / (4. * (wi.z() + wo.z() - wi.z() * wo.z()));
+
+        let attenuation = self
+            .albedo
+            .filtered_value(rec.u, rec.v, rec.p, rec.footprint())
+            * (d * v * PI);
+        Some((attenuation, r_in.scattered(rec.p, uvw.local(wi))))
+    }
+}
+
+fn with_shading_normal<'a>(r_in: Ray, rec: &HitRecord<'a>, normal: Vec3) -> HitRecord<'a> {
+    // The hit record with its normal replaced by a perturbed shading normal. Normals turned away
+    // from the incoming ray can't be shaded, so those fall back to the geometric normal.
+    let mut shading = rec.clone();
+    let normal = normal.unit_vector();
+    if normal.dot(r_in.direction()) < 0. {
+        shading.normal = normal;
+    }
+    shading
+}
+
+// Adds surface detail to a material by bending its normals with a tangent space normal map, where
+// red, green and blue encode the normal along u, v and the surface normal.
+#[derive(Clone)]
+pub struct NormalMapped {
+    base: Arc<AnyMaterial>,
+    normal_map: Arc<AnyTexture>,
+    // Scale of the tilt away from the surface normal, 1 as encoded in the map
+    strength: f64,
+}
+
+impl NormalMapped {
+    pub fn new(
+        base: impl Into<AnyMaterial>,
+        normal_map: impl Into<AnyTexture>,
+        strength: f64,
+    ) -> Self {
+        Self {
+            base: Arc::new(base.into()),
+            normal_map: Arc::new(normal_map.into()),
+            strength,
+        }
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
+        // Build the tangent frame around the normal from the direction of increasing u, with the
+        // bitangent pointing along increasing v.
+        let n = rec.normal;
+        let t = (rec.dpdu - rec.dpdu.dot(n) * n).unit_vector();
+        let b = n.cross(t);
+        let b = if b.dot(rec.dpdv) < 0. { -b } else { b };
+
+        let c = self
+            .normal_map
+            .filtered_value(rec.u, rec.v, rec.p, rec.footprint());
+        let local = Vec3::new(
+            self.strength * (2. * c.x() - 1.),
+            self.strength * (2. * c.y() - 1.),
+            2. * c.z() - 1.,
+        );
+        let normal = local.x() * t + local.y() * b + local.z() * n;
+
+        self.base
+            .scatter(r_in, &with_shading_normal(r_in, rec, normal))
+    }
+
+    fn is_opaque(&self, r: Ray, rec: &HitRecord<'_>) -> bool {
+        self.base.is_opaque(r, rec)
+    }
+}
+
+// Step in texture coordinates for the finite differences of the height.
+const BUMP_DELTA: f64 = 1e-3;
+
+// Adds surface detail to a material by bending its normals as if the surface were displaced along
+// them by a height texture, read as the average of its channels.
+#[derive(Clone)]
+pub struct BumpMapped {
+    base: Arc<AnyMaterial>,
+    height: Arc<AnyTexture>,
+    // Displacement in scene units for a height of 1
+    scale: f64,
+}
+
+impl BumpMapped {
+    pub fn new(base: impl Into<AnyMaterial>, height: impl Into<AnyTexture>, scale: f64) -> Self {
+        Self {
+            base: Arc::new(base.into()),
+            height: Arc::new(height.into()),
+            scale,
+        }
+    }
+}
+
+impl Material for BumpMapped {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
+        // Estimate the slope of the height along u and v with forward differences, moving the
+        // lookup point along with the texture coordinates for solid textures.
+        let height = |du: f64, dv: f64| {
+            let p = rec.p + du * rec.dpdu + dv * rec.dpdv;
+            self.scale * scalar(&self.height, rec.u + du, rec.v + dv, p, rec.footprint())
+        };
+        let h = height(0., 0.);
+        let dhdu = (height(BUMP_DELTA, 0.) - h) / BUMP_DELTA;
+        let dhdv = (height(0., BUMP_DELTA) - h) / BUMP_DELTA;
+
+        // Tangents of the displaced surface, ignoring the change of the normal itself.
+        let n = rec.normal;
+        let dpdu = rec.dpdu + dhdu * n;
+        let dpdv = rec.dpdv + dhdv * n;
+        let normal = dpdu.cross(dpdv);
+        let normal = if normal.dot(n) < 0. { -normal } else { normal };
+
+        self.base
+            .scatter(r_in, &with_shading_normal(r_in, rec, normal))
+    }
+
+    fn is_opaque(&self, r: Ray, rec: &HitRecord<'_>) -> bool {
+        self.base.is_opaque(r, rec)
+    }
+}
+
+// How a cutout decides where its surface is there.
+#[derive(Clone, Copy)]
+pub enum AlphaMode {
+    // Solid where the opacity is at least the threshold, and cut out elsewhere
+    Threshold(f64),
+    // Solid with the probability of the opacity, giving soft edges and semi-transparency
+    Stochastic,
+}
+
+// Cuts holes into a material where the alpha channel of an opacity texture is low, for leaves on
+// flat cards or decals.
+#[derive(Clone)]
+pub struct Cutout {
+    base: Arc<AnyMaterial>,
+    opacity: Arc<AnyTexture>,
+    mode: AlphaMode,
+}
+
+impl Cutout {
+    pub fn new(
+        base: impl Into<AnyMaterial>,
+        opacity: impl Into<AnyTexture>,
+        mode: AlphaMode,
+    ) -> Self {
+        Self {
+            base: Arc::new(base.into()),
+            opacity: Arc::new(opacity.into()),
+            mode,
+        }
+    }
+}
+
+impl Material for Cutout {
+    fn scatter(&self, r_in: Ray, rec: &HitRecord<'_>) -> Option<(Color, Ray)> {
+        self.base.scatter(r_in, rec)
+    }
+
+    fn is_opaque(&self, r: Ray, rec: &HitRecord<'_>) -> bool {
+        let alpha = self
+            .opacity
+            .filtered_alpha(rec.u, rec.v, rec.p, rec.footprint_along(r));
+        let solid = match self.mode {
+            AlphaMode::Threshold(threshold) => alpha >= threshold,
+            AlphaMode::Stochastic => random_double() < alpha,
+        };
+        solid && self.base.is_opaque(r, rec)
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{
+    color::Color,
+    rtweekend::{random_double, random_int_min_max},
+    spectrum::rgb_to_spectrum,
+};
+
+// A homogeneous medium filling the inside of a refractive object.
+#[derive(Clone, Copy, Default)]
+pub struct Medium {
+    // Absorption coefficient per unit distance, per color channel
+    absorption: Color,
+    // Scattering coefficient per unit distance, per color channel
+    scattering: Color,
+}
+
+impl Medium {
+    pub fn new(absorption: Color) -> Self {
+        Self {
+            absorption,
+            scattering: Color::default(),
+        }
+    }
+
+    // A medium that leaves `color` of the light after it travels `distance` through it, which is
+    // easier to pick than the raw coefficients.
+    pub fn from_color(color: Color, distance: f64) -> Self {
+        let coefficient = |c: f64| -c.max(1e-6).ln() / distance;
+        Self::new(Color::new(
+            coefficient(color.x()),
+            coefficient(color.y()),
+            coefficient(color.z()),
+        ))
+    }
+
+    // A scattering medium that looks like `albedo` once light has bounced around in it, where
+    // light travels `mean_free_path` on average between interactions.
+    pub fn from_albedo(albedo: Color, mean_free_path: Color) -> Self {
+        // Invert the multiple scattering albedo to the single scattering albedo, with the fit from
+        // Chiang et al., "Practical and Controllable Subsurface Scattering for Production Path
+        // Tracing".
+        let single_scattering = |a: f64| {
+            let a = a.clamp(0., 0.999);
+            let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+            1. - s * s
+        };
+
+        let mut absorption = Color::default();
+        let mut scattering = Color::default();
+        for c in 0..3 {
+            let extinction = 1. / mean_free_path[c].max(1e-6);
+            scattering[c] = single_scattering(albedo[c]) * extinction;
+            absorption[c] = extinction - scattering[c];
+        }
+
+        Self {
+            absorption,
+            scattering,
+        }
+    }
+
+    // Beer–Lambert law: the fraction of light left after travelling `distance` through the medium.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let extinction = self.absorption + self.scattering;
+        let mut transmittance = Color::new(1., 1., 1.);
+        for c in 0..3 {
+            if extinction[c] > 0. {
+                transmittance[c] = (-extinction[c] * distance).exp();
+            }
+        }
+        transmittance
+    }
+
+    pub fn is_scattering(&self) -> bool {
+        !self.scattering.near_zero()
+    }
+
+    // The medium as seen by light of a single color channel, or of a single wavelength, with the
+    // same coefficients in all channels.
+    pub fn channel(&self, channel: i32) -> Self {
+        let grey = |c: Color| Color::new(c[channel], c[channel], c[channel]);
+        Self {
+            absorption: grey(self.absorption),
+            scattering: grey(self.scattering),
+        }
+    }
+
+    pub fn at_wavelength(&self, wavelength: f64) -> Self {
+        let grey = |c: Color| {
+            let value = rgb_to_spectrum(c, wavelength);
+            Color::new(value, value, value)
+        };
+        Self {
+            absorption: grey(self.absorption),
+            scattering: grey(self.scattering),
+        }
+    }
+
+    // Samples how far a ray travels through the medium before it scatters, up to `max_distance`
+    // where it leaves the medium. Returns the distance, and the weight of the light carried along
+    // the way divided by the sampling density.
+    pub fn sample_distance(&self, max_distance: f64) -> (f64, Color) {
+        if !self.is_scattering() {
+            return (max_distance, self.transmittance(max_distance));
+        }
+
+        // Sample the distance for a randomly chosen color channel, weighting by the density of
+        // sampling it from any of the channels. With the same coefficients in all channels the
+        // weight is simply the albedo.
+        let extinction = self.absorption + self.scattering;
+        let channel = random_int_min_max(0, 2);
+        let distance = -(1. - random_double()).ln() / extinction[channel];
+
+        if distance >= max_distance {
+            let transmittance = self.transmittance(max_distance);
+            let probability = (transmittance.x() + transmittance.y() + transmittance.z()) / 3.;
+            return (max_distance, transmittance / probability);
+        }
+
+        let transmittance = self.transmittance(distance);
+        let density = extinction * transmittance;
+        let pdf = (density.x() + density.y() + density.z()) / 3.;
+        (distance, self.scattering * transmittance / pdf)
+    }
+}
@@ -0,0 +1,226 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    bvh::BvhNode,
+    hittable_list::HittableList,
+    material::AnyMaterial,
+    rtweekend::PI,
+    texture::{AnyTexture, Texture},
+    triangle::Triangle,
+    vec3::{Point3, Vec3},
+};
+
+// Most times a mesh is split in four to reach the target edge length.
+const MAX_SUBDIVISIONS: i32 = 8;
+// Vertices closer together than this along every axis count as the same position.
+const WELD_TOLERANCE: f64 = 1e-9;
+
+// Triangle mesh with per vertex normals and texture coordinates.
+#[derive(Clone)]
+pub struct Mesh {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    // Vertex indices of each triangle, in either winding order
+    faces: Vec<[usize; 3]>,
+}
+
+impl Mesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        faces: Vec<[usize; 3]>,
+    ) -> Self {
+        Self {
+            positions,
+            normals,
+            uvs,
+            faces,
+        }
+    }
+
+    pub fn sphere(center: Point3, radius: f64, max_edge_length: f64) -> Self {
+        // Latitude-longitude sphere with the same texture coordinates as Sphere, and rows and
+        // columns of vertices close enough together for edges no longer than max_edge_length.
+        let columns = ((2. * PI * radius / max_edge_length).ceil() as usize).max(3);
+        let rows = ((PI * radius / max_edge_length).ceil() as usize).max(2);
+
+        let mut positions = Vec::with_capacity((rows + 1) * (columns + 1));
+        let mut normals = Vec::with_capacity(positions.capacity());
+        let mut uvs = Vec::with_capacity(positions.capacity());
+        for j in 0..=rows {
+            let v = j as f64 / rows as f64;
+            let theta = v * PI;
+            for i in 0..=columns {
+                let u = i as f64 / columns as f64;
+                let phi = u * 2. * PI;
+                let normal = Vec3::new(
+                    -phi.cos() * theta.sin(),
+                    -theta.cos(),
+                    phi.sin() * theta.sin(),
+                );
+                positions.push(center + radius * normal);
+                normals.push(normal);
+                uvs.push((u, v));
+            }
+        }
+
+        // The rows touching the poles only need one triangle per column.
+        let index = |i: usize, j: usize| j * (columns + 1) + i;
+        let mut faces = Vec::with_capacity(2 * rows * columns);
+        for j in 0..rows {
+            for i in 0..columns {
+                let (a, b) = (index(i, j), index(i + 1, j));
+                let (c, d) = (index(i + 1, j + 1), index(i, j + 1));
+                if j != 0 {
+                    faces.push([a, c, b]);
+                }
+                if j != rows - 1 {
+                    faces.push([a, d, c]);
+                }
+            }
+        }
+
+        Self::new(positions, normals, uvs, faces)
+    }
+
+    pub fn subdivide(&self) -> Self {
+        // Split every triangle into four at the midpoints of its edges. Neighboring triangles
+        // share their edge midpoints, so the mesh stays watertight.
+        let mut mesh = self.clone();
+        mesh.faces = Vec::with_capacity(4 * self.faces.len());
+
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |mesh: &mut Self, a: usize, b: usize| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let (ua, va) = mesh.uvs[a];
+                let (ub, vb) = mesh.uvs[b];
+                mesh.positions
+                    .push((mesh.positions[a] + mesh.positions[b]) / 2.);
+                mesh.normals
+                    .push((mesh.normals[a] + mesh.normals[b]).unit_vector());
+                mesh.uvs.push(((ua + ub) / 2., (va + vb) / 2.));
+                mesh.positions.len() - 1
+            })
+        };
+
+        for &[a, b, c] in &self.faces {
+            let ab = midpoint(&mut mesh, a, b);
+            let bc = midpoint(&mut mesh, b, c);
+            let ca = midpoint(&mut mesh, c, a);
+            mesh.faces
+                .extend([[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]);
+        }
+
+        mesh
+    }
+
+    pub fn tessellate(&self, max_edge_length: f64) -> Self {
+        // Subdivide until no edge is longer than max_edge_length. Each round at least halves the
+        // longest edge, so the number of rounds follows from it.
+        let mut rounds = 0;
+        let mut longest = self.max_edge_length();
+        while longest > max_edge_length && rounds < MAX_SUBDIVISIONS {
+            longest /= 2.;
+            rounds += 1;
+        }
+        if longest > max_edge_length {
+            eprintln!(
+                "WARNING: Mesh edges can only be subdivided down to {longest}, \
+                 not {max_edge_length}."
+            );
+        }
+
+        let mut mesh = self.clone();
+        for _ in 0..rounds {
+            mesh = mesh.subdivide();
+        }
+        mesh
+    }
+
+    fn max_edge_length(&self) -> f64 {
+        self.faces
+            .iter()
+            .flat_map(|&[a, b, c]| [(a, b), (b, c), (c, a)])
+            .map(|(a, b)| (self.positions[a] - self.positions[b]).length())
+            .fold(0., f64::max)
+    }
+
+    pub fn displace(&self, height: impl Into<AnyTexture>, scale: f64) -> Self {
+        // Move each vertex along its normal by the height texture times scale, reading the average
+        // of the texture's channels. Vertices at the same position, like on texture seams and at
+        // the poles, move together so no cracks open up.
+        let height = height.into();
+        let mut offsets = HashMap::new();
+        let mut mesh = self.clone();
+        for i in 0..mesh.positions.len() {
+            let p = self.positions[i];
+            let n = self.normals[i];
+            let offset = *offsets.entry(Self::key(p)).or_insert_with(|| {
+                let (u, v) = self.uvs[i];
+                let c = height.value(u, v, p);
+                scale * (c.x() + c.y() + c.z()) / 3. * n
+            });
+            mesh.positions[i] = p + offset;
+        }
+
+        mesh.recompute_normals();
+        mesh
+    }
+
+    fn recompute_normals(&mut self) {
+        // Area weighted average of the normals of the faces around each position.
+        let mut sums: HashMap<_, Vec3> = HashMap::new();
+        for &[a, b, c] in &self.faces {
+            let (pa, pb, pc) = (self.positions[a], self.positions[b], self.positions[c]);
+            let face_normal = (pb - pa).cross(pc - pa);
+            for p in [pa, pb, pc] {
+                *sums.entry(Self::key(p)).or_default() += face_normal;
+            }
+        }
+
+        for (p, n) in self.positions.iter().zip(&mut self.normals) {
+            let sum = sums.get(&Self::key(*p)).copied().unwrap_or_default();
+            if !sum.near_zero() {
+                *n = sum.unit_vector();
+            }
+        }
+    }
+
+    fn key(p: Point3) -> [i64; 3] {
+        // Snap positions to a fine grid, so copies of a vertex that only differ by rounding, like
+        // sin(2π) against 0 or -0 against 0, weld together.
+        let snap = |x: f64| (x / WELD_TOLERANCE).round() as i64;
+        [snap(p.x()), snap(p.y()), snap(p.z())]
+    }
+
+    pub fn build(self, mat: impl Into<AnyMaterial>) -> BvhNode {
+        // Turn the mesh into triangles sharing its data and the material, in a BVH of their own.
+        let faces = self.faces.len();
+        let mesh = Arc::new(self);
+        let mat = Arc::new(mat.into());
+
+        let mut triangles = HittableList::new();
+        for face in 0..faces {
+            triangles.add(Triangle::new(mesh.clone(), face, mat.clone()));
+        }
+        BvhNode::from_list(triangles)
+    }
+
+    pub fn vertices(&self, face: usize) -> [usize; 3] {
+        self.faces[face]
+    }
+
+    pub fn position(&self, vertex: usize) -> Point3 {
+        self.positions[vertex]
+    }
+
+    pub fn normal(&self, vertex: usize) -> Vec3 {
+        self.normals[vertex]
+    }
+
+    pub fn uv(&self, vertex: usize) -> (f64, f64) {
+        self.uvs[vertex]
+    }
+}
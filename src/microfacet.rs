@@ -0,0 +1,171 @@
+use crate::{
+    rtweekend::{random_double, PI},
+    vec3::Vec3,
+};
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals. Directions are given in the local
+// shading frame, with the surface normal along +z.
+#[derive(Clone, Copy)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(roughness_x: f64, roughness_y: f64) -> Self {
+        // Perceptually linear roughness maps to alpha by squaring it.
+        Self {
+            alpha_x: (roughness_x * roughness_x).max(1e-4),
+            alpha_y: (roughness_y * roughness_y).max(1e-4),
+        }
+    }
+
+    fn lambda(self, w: Vec3) -> f64 {
+        // Smith's auxiliary function, measuring the microfacet area masked in direction w.
+        let cos2_theta = w.z() * w.z();
+        if cos2_theta == 0. {
+            return f64::INFINITY;
+        }
+        let alpha2_tan2_theta = (self.alpha_x * self.alpha_x * w.x() * w.x()
+            + self.alpha_y * self.alpha_y * w.y() * w.y())
+            / cos2_theta;
+        ((1. + alpha2_tan2_theta).sqrt() - 1.) / 2.
+    }
+
+    pub fn g1(self, w: Vec3) -> f64 {
+        // Fraction of microfacets visible from direction w.
+        1. / (1. + self.lambda(w))
+    }
+
+    pub fn g(self, wo: Vec3, wi: Vec3) -> f64 {
+        // Fraction of microfacets visible from both wo and wi.
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    pub fn sample_visible_normal(self, wo: Vec3) -> Vec3 {
+        // Sample a microfacet normal from the distribution of normals visible from wo (Heitz 2018).
+
+        // Stretch wo to the configuration of a hemisphere with alpha 1.
+        let mut wh = Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).unit_vector();
+        if wh.z() < 0. {
+            wh = -wh;
+        }
+
+        // Build an orthonormal basis around the stretched direction.
+        let t1 = if wh.z() < 0.99999 {
+            Vec3::new(0., 0., 1.).cross(wh).unit_vector()
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let t2 = wh.cross(t1);
+
+        // Sample a point on the projected disk, warped towards the visible half of it.
+        let r = random_double().sqrt();
+        let phi = 2. * PI * random_double();
+        let px = r * phi.cos();
+        let h = (1. - px * px).sqrt();
+        let s = (1. + wh.z()) / 2.;
+        let py = (1. - s) * h + s * r * phi.sin();
+
+        // Project the point back onto the hemisphere and unstretch it.
+        let pz = (1. - px * px - py * py).max(0.).sqrt();
+        let nh = px * t1 + py * t2 + pz * wh;
+        Vec3::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(1e-6),
+        )
+        .unit_vector()
+    }
+}
+
+pub fn fresnel_conductor(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    // Exact unpolarized Fresnel reflectance of a conductor with complex index of refraction
+    // eta + ik, for light arriving from air.
+    let cos_theta_i = cos_theta_i.clamp(0., 1.);
+    let cos2_theta_i = cos_theta_i * cos_theta_i;
+    let sin2_theta_i = 1. - cos2_theta_i;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2_theta_i;
+    let a2_plus_b2 = (t0 * t0 + 4. * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2_theta_i;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.).sqrt();
+    let t2 = 2. * cos_theta_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2_theta_i * a2_plus_b2 + sin2_theta_i * sin2_theta_i;
+    let t4 = t2 * sin2_theta_i;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    // Exact unpolarized Fresnel reflectance at an interface with relative index of refraction
+    // eta (transmitted side over incident side). Negative cosines mean light arriving from the
+    // other side. Returns 1 on total internal reflection.
+    let (cos_theta_i, eta) = if cos_theta_i < 0. {
+        (-cos_theta_i, 1. / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let cos_theta_i = cos_theta_i.min(1.);
+
+    let sin2_theta_i = 1. - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1. {
+        return 1.;
+    }
+    let cos_theta_t = (1. - sin2_theta_t).sqrt();
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.
+}
+
+pub fn fresnel_thin_film(
+    cos_theta_i: f64,
+    eta_incident: f64,
+    eta_film: f64,
+    eta_substrate: f64,
+    thickness: f64,
+    wavelength: f64,
+) -> f64 {
+    // Unpolarized reflectance of a thin film between the incident medium and a substrate, summing
+    // the light reflected back and forth inside the film with its phase (Airy's formula). The
+    // thickness and the wavelength are in nanometers.
+    let cos_theta_i = cos_theta_i.clamp(0., 1.);
+    let sin2_theta_i = 1. - cos_theta_i * cos_theta_i;
+
+    // Light is totally reflected when it can't enter the film, or can't leave it into the
+    // substrate and has nowhere else to go.
+    let sin2_theta_film = sin2_theta_i * (eta_incident / eta_film).powi(2);
+    let sin2_theta_substrate = sin2_theta_i * (eta_incident / eta_substrate).powi(2);
+    if sin2_theta_film >= 1. || sin2_theta_substrate >= 1. {
+        return 1.;
+    }
+    let cos_theta_film = (1. - sin2_theta_film).sqrt();
+    let cos_theta_substrate = (1. - sin2_theta_substrate).sqrt();
+
+    // Phase difference between successive reflections out of the film.
+    let phase = 4. * PI * eta_film * thickness * cos_theta_film / wavelength;
+
+    let airy = |r12: f64, r23: f64| {
+        let interference = 2. * r12 * r23 * phase.cos();
+        (r12 * r12 + r23 * r23 + interference) / (1. + r12 * r12 * r23 * r23 + interference)
+    };
+
+    let (n1, n2, n3) = (eta_incident, eta_film, eta_substrate);
+    let (c1, c2, c3) = (cos_theta_i, cos_theta_film, cos_theta_substrate);
+    let r_perpendicular = airy(
+        (n1 * c1 - n2 * c2) / (n1 * c1 + n2 * c2),
+        (n2 * c2 - n3 * c3) / (n2 * c2 + n3 * c3),
+    );
+    let r_parallel = airy(
+        (n2 * c1 - n1 * c2) / (n2 * c1 + n1 * c2),
+        (n3 * c2 - n2 * c3) / (n3 * c2 + n2 * c3),
+    );
+    (r_parallel + r_perpendicular) / 2.
+}
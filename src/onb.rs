@@ -0,0 +1,48 @@
+use crate::vec3::Vec3;
+
+// Orthonormal basis, with w as the local "up" direction.
+#[derive(Clone, Copy)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn build_from_w(w: Vec3) -> Self {
+        let unit_w = w.unit_vector();
+        let a = if unit_w.x().abs() > 0.9 {
+            Vec3::new(0., 1., 0.)
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let v = unit_w.cross(a).unit_vector();
+        let u = unit_w.cross(v);
+
+        Self { u, v, w: unit_w }
+    }
+
+    pub fn build_from_w_and_u(w: Vec3, u: Vec3) -> Self {
+        // Basis around w with u pointing as close to the given direction as possible, such as a
+        // surface tangent, falling back to an arbitrary u when it runs along w.
+        let unit_w = w.unit_vector();
+        let u = u - u.dot(unit_w) * unit_w;
+        if u.near_zero() {
+            return Self::build_from_w(unit_w);
+        }
+        let u = u.unit_vector();
+        let v = unit_w.cross(u);
+
+        Self { u, v, w: unit_w }
+    }
+
+    pub fn local(self, a: Vec3) -> Vec3 {
+        // Transform a vector from basis coordinates to world coordinates.
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+
+    pub fn to_local(self, a: Vec3) -> Vec3 {
+        // Transform a vector from world coordinates to basis coordinates.
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use crate::{
+    color::Color,
+    rtweekend::degrees_to_radians,
+    texture::{AnyTexture, Texture},
+    vec3::Point3,
+};
+
+// Shapes a pattern lays out in texture coordinates, with cells one unit across. Wrap a pattern in
+// a UvTransform to size and turn it.
+#[derive(Clone, Copy)]
+pub enum PatternKind {
+    // Squares alternating between the first and second texture
+    Checker,
+    // Stripes of the first texture running along v, taking `width` of each unit of u
+    Stripes { width: f64 },
+    // Cells of the first texture between lines of the second, `line_width` wide
+    Grid { line_width: f64 },
+    // The first texture at u = 0 blending into the second at u = 1
+    Gradient,
+    // Bricks of the first texture twice as long as they are high, shifted by half a brick every
+    // other row, with mortar of the second texture `mortar_width` wide
+    Bricks { mortar_width: f64 },
+    // Hexagonal tiles of the first texture, with grout of the second texture `grout_width` wide
+    Tiles { grout_width: f64 },
+}
+
+// A pattern in texture coordinates that picks between, or blends, two textures.
+#[derive(Clone)]
+pub struct Pattern {
+    kind: PatternKind,
+    first: Arc<AnyTexture>,
+    second: Arc<AnyTexture>,
+}
+
+impl Pattern {
+    pub fn new(
+        kind: PatternKind,
+        first: impl Into<AnyTexture>,
+        second: impl Into<AnyTexture>,
+    ) -> Self {
+        Self {
+            kind,
+            first: Arc::new(first.into()),
+            second: Arc::new(second.into()),
+        }
+    }
+
+    fn weight(&self, u: f64, v: f64) -> f64 {
+        // How much of the second texture shows at u,v, from 0 to 1.
+        let pick = |second: bool| if second { 1. } else { 0. };
+        match self.kind {
+            PatternKind::Checker => pick((u.floor() + v.floor()).rem_euclid(2.) == 1.),
+            PatternKind::Stripes { width } => pick(u.rem_euclid(1.) >= width),
+            PatternKind::Grid { line_width } => {
+                let near_line = |x: f64| {
+                    let x = x.rem_euclid(1.);
+                    x < line_width / 2. || x > 1. - line_width / 2.
+                };
+                pick(near_line(u) || near_line(v))
+            }
+            PatternKind::Gradient => u.clamp(0., 1.),
+            PatternKind::Bricks { mortar_width } => {
+                // Rows are half a unit high, with x and y measured within the brick.
+                let row = (2. * v).floor();
+                let x = (u + 0.5 * row.rem_euclid(2.)).rem_euclid(1.);
+                let y = v - row / 2.;
+                let half = mortar_width / 2.;
+                pick(x < half || x > 1. - half || y < half || y > 0.5 - half)
+            }
+            PatternKind::Tiles { grout_width } => {
+                // Offset from the nearest tile center, on the lattice of two interleaved
+                // rectangular grids of centers one unit apart.
+                let (w, h) = (1., 3_f64.sqrt());
+                let offset = |cu: f64, cv: f64| {
+                    (
+                        (u - cu).rem_euclid(w) - w / 2.,
+                        (v - cv).rem_euclid(h) - h / 2.,
+                    )
+                };
+                let (a, b) = (offset(0., 0.), offset(w / 2., h / 2.));
+                let (x, y) = if a.0 * a.0 + a.1 * a.1 < b.0 * b.0 + b.1 * b.1 {
+                    a
+                } else {
+                    b
+                };
+                // Distance to the edge of a pointy topped hexagon one unit across its flat sides.
+                let (x, y) = (x.abs(), y.abs());
+                let edge = 0.5 - x.max(0.5 * x + 3_f64.sqrt() / 2. * y);
+                pick(edge < grout_width / 2.)
+            }
+        }
+    }
+
+    fn blend(&self, u: f64, v: f64, lookup: impl Fn(&AnyTexture) -> Color) -> Color {
+        // Only look up the textures that show.
+        match self.weight(u, v) {
+            w if w <= 0. => lookup(&self.first),
+            w if w >= 1. => lookup(&self.second),
+            w => (1. - w) * lookup(&self.first) + w * lookup(&self.second),
+        }
+    }
+}
+
+impl Texture for Pattern {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        self.blend(u, v, |texture| texture.value(u, v, p))
+    }
+
+    fn filtered_value(&self, u: f64, v: f64, p: Point3, footprint: f64) -> Color {
+        self.blend(u, v, |texture| texture.filtered_value(u, v, p, footprint))
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: Point3) -> f64 {
+        let w = self.weight(u, v);
+        (1. - w) * self.first.alpha(u, v, p) + w * self.second.alpha(u, v, p)
+    }
+
+    fn filtered_alpha(&self, u: f64, v: f64, p: Point3, footprint: f64) -> f64 {
+        let w = self.weight(u, v);
+        (1. - w) * self.first.filtered_alpha(u, v, p, footprint)
+            + w * self.second.filtered_alpha(u, v, p, footprint)
+    }
+}
+
+// Moves the texture coordinates of a texture: scaled, then rotated counterclockwise about the
+// origin, then offset.
+#[derive(Clone)]
+pub struct UvTransform {
+    texture: Arc<AnyTexture>,
+    scale: (f64, f64),
+    // Radians
+    rotation: f64,
+    offset: (f64, f64),
+}
+
+impl UvTransform {
+    pub fn new(texture: impl Into<AnyTexture>) -> Self {
+        Self {
+            texture: Arc::new(texture.into()),
+            scale: (1., 1.),
+            rotation: 0.,
+            offset: (0., 0.),
+        }
+    }
+
+    // How many times the texture repeats along u and v.
+    pub fn with_scale(mut self, u: f64, v: f64) -> Self {
+        self.scale = (u, v);
+        self
+    }
+
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = degrees_to_radians(degrees);
+        self
+    }
+
+    pub fn with_offset(mut self, u: f64, v: f64) -> Self {
+        self.offset = (u, v);
+        self
+    }
+
+    fn stretch(&self) -> f64 {
+        // How much wider footprints get in the transformed coordinates.
+        self.scale.0.abs().max(self.scale.1.abs())
+    }
+
+    fn transform(&self, u: f64, v: f64) -> (f64, f64) {
+        let (u, v) = (u * self.scale.0, v * self.scale.1);
+        let (sin, cos) = self.rotation.sin_cos();
+        (
+            cos * u - sin * v + self.offset.0,
+            sin * u + cos * v + self.offset.1,
+        )
+    }
+}
+
+impl Texture for UvTransform {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        let (u, v) = self.transform(u, v);
+        self.texture.value(u, v, p)
+    }
+
+    fn filtered_value(&self, u: f64, v: f64, p: Point3, footprint: f64) -> Color {
+        let (u, v) = self.transform(u, v);
+        self.texture
+            .filtered_value(u, v, p, footprint * self.stretch())
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: Point3) -> f64 {
+        let (u, v) = self.transform(u, v);
+        self.texture.alpha(u, v, p)
+    }
+
+    fn filtered_alpha(&self, u: f64, v: f64, p: Point3, footprint: f64) -> f64 {
+        let (u, v) = self.transform(u, v);
+        self.texture
+            .filtered_alpha(u, v, p, footprint * self.stretch())
+    }
+}
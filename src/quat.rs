@@ -0,0 +1,79 @@
+use std::ops::Mul;
+
+use crate::{rtweekend::degrees_to_radians, vec3::Vec3};
+
+#[derive(Clone, Copy)]
+pub struct Quat {
+    w: f64,
+    v: Vec3,
+}
+
+impl Quat {
+    pub fn new(w: f64, v: Vec3) -> Self {
+        Self { w, v }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(1., Vec3::default())
+    }
+
+    pub fn from_axis_angle(axis: Vec3, degrees: f64) -> Self {
+        let half_angle = degrees_to_radians(degrees) / 2.;
+        Self::new(half_angle.cos(), half_angle.sin() * axis.unit_vector())
+    }
+
+    pub fn dot(self, other: Self) -> f64 {
+        self.w * other.w + self.v.dot(other.v)
+    }
+
+    pub fn conjugate(self) -> Self {
+        Self::new(self.w, -self.v)
+    }
+
+    pub fn normalized(self) -> Self {
+        let length = self.dot(self).sqrt();
+        Self::new(self.w / length, self.v / length)
+    }
+
+    pub fn rotate(self, p: Vec3) -> Vec3 {
+        // Rotate p by the unit quaternion, expanded form of q * p * q^-1.
+        let t = 2. * self.v.cross(p);
+        p + self.w * t + self.v.cross(t)
+    }
+
+    pub fn slerp(self, other: Self, t: f64) -> Self {
+        // Spherical linear interpolation along the shortest arc between two unit quaternions.
+        let mut cos_theta = self.dot(other);
+        let other = if cos_theta < 0. {
+            cos_theta = -cos_theta;
+            Self::new(-other.w, -other.v)
+        } else {
+            other
+        };
+
+        // Fall back to normalized linear interpolation when the quaternions are nearly equal.
+        let (s0, s1) = if cos_theta > 0.9995 {
+            (1. - t, t)
+        } else {
+            let theta = cos_theta.acos();
+            let sin_theta = theta.sin();
+            (
+                ((1. - t) * theta).sin() / sin_theta,
+                (t * theta).sin() / sin_theta,
+            )
+        };
+
+        Self::new(s0 * self.w + s1 * other.w, s0 * self.v + s1 * other.v).normalized()
+    }
+}
+
+impl Mul for Quat {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.w * rhs.w - self.v.dot(rhs.v),
+            self.w * rhs.v + rhs.w * self.v + self.v.cross(rhs.v),
+        )
+    }
+}
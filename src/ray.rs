@@ -1,10 +1,49 @@
-use crate::vec3::{Point3, Vec3};
+use crate::{
+    medium::Medium,
+    vec3::{Point3, Vec3},
+};
+
+// Rays through the neighboring pixels to the right and below, telling how large the footprint of
+// the pixel is where the ray lands.
+#[derive(Clone, Copy)]
+pub struct Differentials {
+    pub rx_origin: Point3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Point3,
+    pub ry_direction: Vec3,
+}
+
+impl Differentials {
+    // The same rays moved into another space, like the ray itself when it enters the object space
+    // of a transformed object.
+    pub fn transformed(
+        self,
+        point: impl Fn(Point3) -> Point3,
+        vector: impl Fn(Vec3) -> Vec3,
+    ) -> Self {
+        Self {
+            rx_origin: point(self.rx_origin),
+            rx_direction: vector(self.rx_direction),
+            ry_origin: point(self.ry_origin),
+            ry_direction: vector(self.ry_direction),
+        }
+    }
+}
 
 #[derive(Clone, Copy, Default)]
 pub struct Ray {
     orig: Point3,
     dir: Vec3,
     tm: f64,
+    // The medium the ray is travelling through
+    medium: Medium,
+    // Wavelength in nanometers carried by rays of the spectral renderer
+    wavelength: Option<f64>,
+    // Color channel carried alone by rays of the RGB renderer, once they took a random walk
+    // through a scattering medium
+    channel: Option<i32>,
+    // Carried by camera rays, and by their mirror reflections and refractions
+    differentials: Option<Differentials>,
 }
 
 impl Ray {
@@ -13,6 +52,10 @@ impl Ray {
             orig: origin,
             dir: direction,
             tm: time,
+            medium: Medium::default(),
+            wavelength: None,
+            channel: None,
+            differentials: None,
         }
     }
 
@@ -31,4 +74,57 @@ impl Ray {
     pub fn time(self) -> f64 {
         self.tm
     }
+
+    pub fn medium(self) -> Medium {
+        self.medium
+    }
+
+    pub fn with_medium(self, medium: Medium) -> Self {
+        Self { medium, ..self }
+    }
+
+    pub fn wavelength(self) -> Option<f64> {
+        self.wavelength
+    }
+
+    pub fn with_wavelength(self, wavelength: f64) -> Self {
+        Self {
+            wavelength: Some(wavelength),
+            ..self
+        }
+    }
+
+    pub fn channel(self) -> Option<i32> {
+        self.channel
+    }
+
+    pub fn with_channel(self, channel: i32) -> Self {
+        Self {
+            channel: Some(channel),
+            ..self
+        }
+    }
+
+    pub fn differentials(self) -> Option<Differentials> {
+        self.differentials
+    }
+
+    pub fn with_differentials(self, differentials: Option<Differentials>) -> Self {
+        Self {
+            differentials,
+            ..self
+        }
+    }
+
+    // A ray continuing the path of this one from a scattering event, at the same time and
+    // wavelength and in the same medium. It loses the differentials, which only specular
+    // materials know how to carry on.
+    pub fn scattered(self, origin: Point3, direction: Vec3) -> Self {
+        Self {
+            orig: origin,
+            dir: direction,
+            differentials: None,
+            ..self
+        }
+    }
 }
@@ -1,58 +1,77 @@
-use std::{process, sync::Arc};
+use std::{mem, path::Path, sync::Arc};
 
-use image::io::Reader as ImageReader;
+use image::{io::Reader as ImageReader, ColorType, ImageResult};
 
-const BYTES_PER_PIXEL: i32 = 3;
+use crate::texture_cache::TextureCache;
 
+pub const CHANNELS_PER_PIXEL: i32 = 4;
+
+// How the values of an 8 or 16 bit image are encoded. Floating point images are always linear.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    // Colors encoded with the sRGB transfer curve, like most photos and painted textures
+    Srgb,
+    // Raw data used as is, like normal, roughness and height maps
+    Linear,
+}
+
+// An image read through a texture cache, which decodes it when it's first sampled and keeps as
+// much of it in memory as its budget allows.
 #[derive(Clone)]
 pub struct RtwImage {
-    data: Arc<[u8]>,
-    image_width: i32,
-    image_height: i32,
-    bytes_per_scanline: i32,
+    cache: TextureCache,
+    // The image's index in the cache
+    id: usize,
+    // Width and height of each mip level, from the full image down to a single pixel
+    level_sizes: Arc<[(i32, i32)]>,
 }
 
 impl RtwImage {
-    pub fn new(filename: &str) -> Self {
-        let mut image_width = 0;
-        let mut image_height = 0;
-
-        let data = (|| {
-            let image = ImageReader::open(filename).ok()?.decode().ok()?.into_rgb8();
-            image_width = image.width() as i32;
-            image_height = image.height() as i32;
-            Some(image.into_vec().into())
-        })();
-
-        let Some(data) = data else {
-            eprintln!("ERROR: Could not load image file {filename}.");
-            process::exit(1)
-        };
+    pub fn new(filename: &str) -> ImageResult<Self> {
+        Self::load(filename, ColorSpace::Srgb)
+    }
+
+    pub fn load(filename: &str, color_space: ColorSpace) -> ImageResult<Self> {
+        // Read through the cache shared by the whole program.
+        TextureCache::global().image(filename, color_space)
+    }
 
+    pub fn from_cache(cache: TextureCache, id: usize, level_sizes: Arc<[(i32, i32)]>) -> Self {
         Self {
-            data,
-            image_width,
-            image_height,
-            bytes_per_scanline: image_width * BYTES_PER_PIXEL,
+            cache,
+            id,
+            level_sizes,
         }
     }
 
     pub fn width(&self) -> i32 {
-        self.image_width
+        self.level_sizes[0].0
     }
 
     pub fn height(&self) -> i32 {
-        self.image_height
+        self.level_sizes[0].1
     }
 
-    pub fn pixel_data(&self, x: i32, y: i32) -> [u8; 3] {
-        // Return the address of the three bytes of the pixel at x,y (or magenta if no data).
-        let x = Self::clamp(x, 0, self.image_width);
-        let y = Self::clamp(y, 0, self.image_height);
+    pub fn mip_levels(&self) -> usize {
+        self.level_sizes.len()
+    }
+
+    pub fn level_size(&self, level: usize) -> (i32, i32) {
+        self.level_sizes[level]
+    }
+
+    pub fn pixel_data(&self, x: i32, y: i32) -> [f32; 4] {
+        // Return the linear RGBA values of the pixel at x,y. Images without an alpha channel are
+        // fully opaque.
+        self.texel(0, x, y)
+    }
 
-        self.data[(y * self.bytes_per_scanline + x * BYTES_PER_PIXEL) as usize..][..3]
-            .try_into()
-            .unwrap()
+    pub fn texel(&self, level: usize, x: i32, y: i32) -> [f32; 4] {
+        // Return the linear RGBA values of the pixel at x,y of the given mip level.
+        let (width, height) = self.level_sizes[level];
+        let x = Self::clamp(x, 0, width);
+        let y = Self::clamp(y, 0, height);
+        self.cache.texel(self.id, level, x, y)
     }
 
     fn clamp(x: i32, low: i32, high: i32) -> i32 {
@@ -66,3 +85,113 @@ impl RtwImage {
         }
     }
 }
+
+// An image decoded at the bit depth of its file, so that 8 bit images take up a quarter of the
+// memory of floats.
+pub struct DecodedImage {
+    width: i32,
+    samples: Samples,
+    color_space: ColorSpace,
+}
+
+// RGBA values row by row, as stored in the file.
+enum Samples {
+    U8(Box<[u8]>),
+    U16(Box<[u16]>),
+    F32(Box<[f32]>),
+}
+
+impl DecodedImage {
+    pub fn pixel(&self, x: i32, y: i32) -> [f32; 4] {
+        // Return the linear RGBA values of the pixel at x,y. Images without an alpha channel are
+        // fully opaque.
+        let start = ((y * self.width + x) * CHANNELS_PER_PIXEL) as usize;
+        let mut pixel = [0.; 4];
+        match &self.samples {
+            Samples::U8(samples) => {
+                let pixels = pixel.iter_mut().zip(&samples[start..]);
+                pixels.for_each(|(p, &s)| *p = s as f32 / u8::MAX as f32);
+            }
+            Samples::U16(samples) => {
+                let pixels = pixel.iter_mut().zip(&samples[start..]);
+                pixels.for_each(|(p, &s)| *p = s as f32 / u16::MAX as f32);
+            }
+            Samples::F32(samples) => pixel.copy_from_slice(&samples[start..start + 4]),
+        }
+
+        // Floating point images and alpha are always linear.
+        let is_float = matches!(self.samples, Samples::F32(_));
+        if self.color_space == ColorSpace::Srgb && !is_float {
+            pixel[..3].iter_mut().for_each(|c| *c = srgb_to_linear(*c));
+        }
+        pixel
+    }
+
+    pub fn bytes(&self) -> usize {
+        match &self.samples {
+            Samples::U8(samples) => samples.len(),
+            Samples::U16(samples) => samples.len() * mem::size_of::<u16>(),
+            Samples::F32(samples) => samples.len() * mem::size_of::<f32>(),
+        }
+    }
+}
+
+fn half_size(width: i32, height: i32) -> (i32, i32) {
+    ((width / 2).max(1), (height / 2).max(1))
+}
+
+pub fn downsample_taps(i: i32, above: i32, size: i32) -> [(i32, f32); 4] {
+    // Return the pixels of a mip level that pixel i of the level below it covers along one axis,
+    // each with the share of it they make up. Levels of even size halve evenly into pairs, while
+    // the pixels of odd sized ones are spread over three pixels above, partly covering the outer
+    // two, so that every pixel is counted. Unused taps have no weight.
+    let scale = above as f64 / size as f64;
+    let (start, end) = (i as f64 * scale, (i + 1) as f64 * scale);
+    let mut taps = [(0, 0.); 4];
+    for (k, tap) in taps.iter_mut().enumerate() {
+        let p = start.floor() as i32 + k as i32;
+        let covered = end.min((p + 1) as f64) - start.max(p as f64);
+        if p < above && covered > 0. {
+            *tap = (p, (covered / scale) as f32);
+        }
+    }
+    taps
+}
+
+pub fn mip_level_sizes(width: i32, height: i32) -> Vec<(i32, i32)> {
+    let mut sizes = vec![(width, height)];
+    while let Some(&(width, height)) = sizes.last().filter(|&&(w, h)| w > 1 || h > 1) {
+        sizes.push(half_size(width, height));
+    }
+    sizes
+}
+
+pub fn decode(path: &Path, color_space: ColorSpace) -> ImageResult<DecodedImage> {
+    // Decode 8 and 16 bit images at their bit depth, and the rest to floats.
+    let image = ImageReader::open(path)?.decode()?;
+    let width = image.width() as i32;
+    let samples = match image.color() {
+        ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8 => {
+            Samples::U8(image.into_rgba8().into_raw().into())
+        }
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+            Samples::U16(image.into_rgba16().into_raw().into())
+        }
+        _ => Samples::F32(image.into_rgba32f().into_raw().into()),
+    };
+
+    Ok(DecodedImage {
+        width,
+        samples,
+        color_space,
+    })
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    // Inverse of the sRGB transfer curve.
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use crate::rtweekend::random_double;
+
+// How efficiently the shutter passes light over the time it is open.
+#[derive(Clone, Default)]
+pub enum ShutterShape {
+    // Fully open for the whole interval
+    #[default]
+    Box,
+    // Opening linearly up to the middle of the interval and closing linearly after it
+    Triangle,
+    // Tabulated efficiency curve spread evenly over the interval
+    Custom(ShutterCurve),
+}
+
+impl ShutterShape {
+    pub fn sample(&self) -> f64 {
+        // Returns a random fraction of the open interval in [0,1), distributed according to the
+        // shutter efficiency.
+        match self {
+            Self::Box => random_double(),
+            Self::Triangle => 0.5 * (random_double() + random_double()),
+            Self::Custom(curve) => curve.sample(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ShutterCurve {
+    // Cumulative distribution of the efficiency samples, ending at 1
+    cdf: Arc<[f64]>,
+}
+
+impl ShutterCurve {
+    pub fn new(efficiency: &[f64]) -> Self {
+        let mut total = 0.;
+        let mut cdf: Vec<_> = efficiency
+            .iter()
+            .map(|e| {
+                total += e.max(0.);
+                total
+            })
+            .collect();
+
+        if cdf.is_empty() {
+            // Without any samples there's nothing to go on, so the shutter is fully open.
+            return Self { cdf: [1.].into() };
+        }
+        if total > 0. {
+            cdf.iter_mut().for_each(|c| *c /= total);
+        } else {
+            // A curve that never lets light through degenerates into a box shutter.
+            let n = cdf.len() as f64;
+            cdf.iter_mut()
+                .enumerate()
+                .for_each(|(i, c)| *c = (i + 1) as f64 / n);
+        }
+
+        Self { cdf: cdf.into() }
+    }
+
+    fn sample(&self) -> f64 {
+        // Invert the piecewise constant distribution: find the segment the random value falls in,
+        // then place it uniformly within that segment.
+        let u = random_double();
+        let i = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .min(self.cdf.len() - 1);
+        let lo = if i == 0 { 0. } else { self.cdf[i - 1] };
+        let hi = self.cdf[i];
+        let frac = if hi > lo { (u - lo) / (hi - lo) } else { 0.5 };
+
+        (i as f64 + frac) / self.cdf.len() as f64
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum ShutterMode {
+    // Every scanline is exposed over the same interval
+    #[default]
+    Global,
+    // Scanlines are exposed one after another from top to bottom, the exposure of the last
+    // scanline starting `readout` later than the first one
+    Rolling {
+        readout: f64,
+    },
+}
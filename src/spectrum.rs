@@ -0,0 +1,157 @@
+use std::sync::OnceLock;
+
+use crate::{color::Color, lens::D_LINE_WAVELENGTH, vec3::Vec3};
+
+// Range of visible wavelengths in nanometers sampled by the spectral renderer.
+pub const LAMBDA_MIN: f64 = 360.;
+pub const LAMBDA_MAX: f64 = 830.;
+
+// Wavelength dependent index of refraction, with wavelengths in nanometers.
+#[derive(Clone, Copy)]
+pub enum Ior {
+    // The same index for every wavelength
+    Constant(f64),
+    // n = a + b / λ², with λ in micrometers
+    Cauchy { a: f64, b: f64 },
+    // n² = 1 + Σ b λ² / (λ² - c), with λ in micrometers
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Ior {
+    pub fn bk7() -> Self {
+        // Schott N-BK7 borosilicate crown glass
+        Self::Sellmeier {
+            b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+            c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+        }
+    }
+
+    pub fn sf11() -> Self {
+        // Schott SF11 dense flint glass
+        Self::Sellmeier {
+            b: [1.737_596_95, 0.313_747_346, 1.898_781_01],
+            c: [0.013_188_707, 0.062_306_814_2, 155.236_29],
+        }
+    }
+
+    pub fn diamond() -> Self {
+        Self::Sellmeier {
+            b: [0.3306, 4.3356, 0.],
+            c: [0.175 * 0.175, 0.106 * 0.106, 0.],
+        }
+    }
+
+    // The index of refraction at the given wavelength, or at the d-line for rays that don't carry
+    // a wavelength.
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let l = wavelength.unwrap_or(D_LINE_WAVELENGTH) / 1000.;
+        let l2 = l * l;
+        match *self {
+            Self::Constant(n) => n,
+            Self::Cauchy { a, b } => a + b / l2,
+            Self::Sellmeier { b, c } => {
+                (1. + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()).sqrt()
+            }
+        }
+    }
+}
+
+impl From<f64> for Ior {
+    fn from(n: f64) -> Self {
+        Self::Constant(n)
+    }
+}
+
+fn lobe(lambda: f64, mu: f64, sigma1: f64, sigma2: f64) -> f64 {
+    // Gaussian with different widths on either side of its peak
+    let t = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+    (-0.5 * t * t).exp()
+}
+
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    // Multi-lobe fit of the CIE 1931 2° color matching functions from Wyman et al., "Simple
+    // Analytic Approximations to the CIE XYZ Color Matching Functions".
+    let x = 1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8);
+    Vec3::new(x, y, z)
+}
+
+pub fn xyz_to_rgb(xyz: Vec3) -> Color {
+    // CIE XYZ to linear sRGB
+    Color::new(
+        3.2406 * xyz.x() - 1.5372 * xyz.y() - 0.4986 * xyz.z(),
+        -0.9689 * xyz.x() + 1.8758 * xyz.y() + 0.0415 * xyz.z(),
+        0.0557 * xyz.x() - 0.2040 * xyz.y() + 1.0570 * xyz.z(),
+    )
+}
+
+// Smits' basis spectra for upsampling RGB, in 10 bins evenly spread over 380nm to 720nm.
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    // Smits, "An RGB to Spectrum Conversion for Reflectances": a smooth spectrum built from the
+    // shared white part of the color plus the secondary and primary colors making up the rest.
+    let bin = (((lambda - 380.) / 34.).floor() as i32).clamp(0, 9) as usize;
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+
+    let value = if r <= g && r <= b {
+        r * SMITS_WHITE[bin]
+            + if g <= b {
+                (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+            } else {
+                (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+            }
+    } else if g <= r && g <= b {
+        g * SMITS_WHITE[bin]
+            + if r <= b {
+                (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+            } else {
+                (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+            }
+    } else {
+        b * SMITS_WHITE[bin]
+            + if r <= g {
+                (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+            } else {
+                (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+            }
+    };
+
+    value.max(0.)
+}
+
+pub fn spectrum_to_rgb(value: f64, lambda: f64) -> Color {
+    // Estimate of the RGB color of a spectrum from a single uniformly sampled wavelength. The
+    // channels are balanced so that a constant spectrum of 1 comes out as white.
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    let white = WHITE.get_or_init(|| {
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as i32;
+        (0..steps)
+            .map(|i| xyz_to_rgb(cie_xyz(LAMBDA_MIN + i as f64 + 0.5)))
+            .fold(Color::default(), |a, b| a + b)
+    });
+
+    value * (LAMBDA_MAX - LAMBDA_MIN) * xyz_to_rgb(cie_xyz(lambda)) / *white
+}
@@ -2,7 +2,7 @@ use crate::{
     aabb::Aabb,
     hittable::{HitRecord, Hittable},
     interval::Interval,
-    material::AnyMaterial,
+    material::{AnyMaterial, Material},
     ray::Ray,
     rtweekend::PI,
     vec3::{Point3, Vec3},
@@ -94,21 +94,37 @@ impl Hittable for Sphere {
         }
         let sqrtd = discriminant.sqrt();
 
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (-half_b - sqrtd) / a;
-        if !ray_t.surrounds(root) {
-            root = (-half_b + sqrtd) / a;
+        // Find the nearest root that lies in the acceptable range, and where the surface isn't
+        // cut out.
+        for root in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
             if !ray_t.surrounds(root) {
-                return None;
+                continue;
             }
-        }
 
-        let p = r.at(root);
-        let outward_normal = (p - center) / self.radius;
-        let (u, v) = self.get_sphere_uv(outward_normal);
-        let record = HitRecord::new(r, p, outward_normal, &self.mat, root, u, v);
+            let p = r.at(root);
+            let outward_normal = (p - center) / self.radius;
+            let (u, v) = self.get_sphere_uv(outward_normal);
+            let mut record = HitRecord::new(r, p, outward_normal, &self.mat, root, u, v);
+
+            // Derivatives of the position along the angles u and v are measured in, which vanish
+            // at the poles. The normal changes along with the position, scaled by the radius.
+            let (x, y, z) = (outward_normal.x(), outward_normal.y(), outward_normal.z());
+            let sin_theta = (1. - y * y).max(0.).sqrt();
+            if sin_theta > 1e-6 {
+                record.dpdu = 2. * PI * self.radius * Vec3::new(z, 0., -x);
+                record.dpdv =
+                    PI * self.radius * Vec3::new(-x * y, sin_theta * sin_theta, -y * z) / sin_theta;
+                record.dndu = record.dpdu / self.radius;
+                record.dndv = record.dpdv / self.radius;
+            }
+            if !self.mat.is_opaque(r, &record) {
+                continue;
+            }
+
+            return Some(record);
+        }
 
-        Some(record)
+        None
     }
 
     fn bounding_box(&self) -> Aabb {
@@ -1,12 +1,34 @@
 use std::sync::Arc;
 
 use enum_dispatch::enum_dispatch;
+use image::ImageResult;
 
-use crate::{color::Color, interval::Interval, rtw_image::RtwImage, vec3::Point3};
+use crate::{
+    color::Color,
+    pattern::{Pattern, UvTransform},
+    rtw_image::{ColorSpace, RtwImage},
+    vec3::Point3,
+};
 
 #[enum_dispatch]
 pub trait Texture {
     fn value(&self, u: f64, v: f64, p: Point3) -> Color;
+
+    // The value averaged over a footprint about `footprint` wide in texture coordinates. Only
+    // image textures filter, the rest are sampled at the point.
+    fn filtered_value(&self, u: f64, v: f64, p: Point3, _footprint: f64) -> Color {
+        self.value(u, v, p)
+    }
+
+    // Opacity at the given point, from 0 for fully transparent to 1 for fully opaque.
+    fn alpha(&self, _u: f64, _v: f64, _p: Point3) -> f64 {
+        1.
+    }
+
+    // The opacity averaged over a footprint, like filtered_value.
+    fn filtered_alpha(&self, u: f64, v: f64, p: Point3, _footprint: f64) -> f64 {
+        self.alpha(u, v, p)
+    }
 }
 
 #[enum_dispatch(Texture)]
@@ -15,6 +37,15 @@ pub enum AnyTexture {
     SolidColor,
     CheckerTexture,
     ImageTexture,
+    Pattern,
+    UvTransform,
+}
+
+impl From<f64> for AnyTexture {
+    fn from(value: f64) -> Self {
+        // Constant grey, handy for scalar material parameters.
+        SolidColor::new(Color::new(value, value, value)).into()
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -46,25 +77,21 @@ pub struct CheckerTexture {
 }
 
 impl CheckerTexture {
-    // pub fn new(scale: f64, even: impl Into<AnyTexture>, odd: impl Into<AnyTexture>) -> Self {
-    //     Self {
-    //         inv_scale: 1. / scale,
-    //         even: Arc::new(even.into()),
-    //         odd: Arc::new(odd.into()),
-    //     }
-    // }
-
-    pub fn from_solid(scale: f64, c1: Color, c2: Color) -> Self {
+    pub fn new(scale: f64, even: impl Into<AnyTexture>, odd: impl Into<AnyTexture>) -> Self {
         Self {
             inv_scale: 1. / scale,
-            even: Arc::new(SolidColor::new(c1).into()),
-            odd: Arc::new(SolidColor::new(c2).into()),
+            even: Arc::new(even.into()),
+            odd: Arc::new(odd.into()),
         }
     }
+
+    pub fn from_solid(scale: f64, c1: Color, c2: Color) -> Self {
+        Self::new(scale, SolidColor::new(c1), SolidColor::new(c2))
+    }
 }
 
-impl Texture for CheckerTexture {
-    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+impl CheckerTexture {
+    fn texture_at(&self, p: Point3) -> &AnyTexture {
         let x_integer = (self.inv_scale * p.x()).floor() as i32;
         let y_integer = (self.inv_scale * p.y()).floor() as i32;
         let z_integer = (self.inv_scale * p.z()).floor() as i32;
@@ -72,9 +99,69 @@ impl Texture for CheckerTexture {
         let is_even = (x_integer + y_integer + z_integer) % 2 == 0;
 
         if is_even {
-            self.even.value(u, v, p)
+            &self.even
         } else {
-            self.odd.value(u, v, p)
+            &self.odd
+        }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        self.texture_at(p).value(u, v, p)
+    }
+
+    fn filtered_value(&self, u: f64, v: f64, p: Point3, footprint: f64) -> Color {
+        self.texture_at(p).filtered_value(u, v, p, footprint)
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: Point3) -> f64 {
+        self.texture_at(p).alpha(u, v, p)
+    }
+
+    fn filtered_alpha(&self, u: f64, v: f64, p: Point3, footprint: f64) -> f64 {
+        self.texture_at(p).filtered_alpha(u, v, p, footprint)
+    }
+}
+
+// How an image texture is sampled between and across its pixels.
+#[derive(Clone, Copy)]
+pub enum Filter {
+    // The closest pixel, for a blocky look up close. The default
+    Nearest,
+    // Blend of the four closest pixels
+    Bilinear,
+    // Catmull-Rom spline through the sixteen closest pixels, smoother than bilinear up close
+    Bicubic,
+    // Bilinear lookups in the two mip levels with pixels closest in size to the lookup footprint,
+    // blended, so textures seen from afar don't alias
+    Trilinear,
+}
+
+// What an image texture reads outside of texture coordinates [0,1] x [0,1].
+#[derive(Clone, Copy)]
+pub enum WrapMode {
+    // The pixels along the edge stretch out
+    Clamp,
+    // The image tiles
+    Repeat,
+    // The image tiles, flipping every other copy so the edges match up
+    Mirror,
+}
+
+impl WrapMode {
+    fn wrap(self, i: i32, size: i32) -> i32 {
+        match self {
+            Self::Clamp => i.clamp(0, size - 1),
+            Self::Repeat => i.rem_euclid(size),
+            Self::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
         }
     }
 }
@@ -82,32 +169,162 @@ impl Texture for CheckerTexture {
 #[derive(Clone)]
 pub struct ImageTexture {
     image: RtwImage,
+    filter: Filter,
+    wrap: WrapMode,
 }
 
 impl ImageTexture {
-    pub fn new(filename: &str) -> Self {
+    pub fn new(filename: &str) -> ImageResult<Self> {
+        RtwImage::new(filename).map(Self::from_image)
+    }
+
+    // An image holding data rather than colors, like a height or roughness map, read without
+    // sRGB decoding.
+    pub fn linear(filename: &str) -> ImageResult<Self> {
+        RtwImage::load(filename, ColorSpace::Linear).map(Self::from_image)
+    }
+
+    pub fn from_image(image: RtwImage) -> Self {
         Self {
-            image: RtwImage::new(filename),
+            image,
+            filter: Filter::Nearest,
+            wrap: WrapMode::Clamp,
         }
     }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: WrapMode) -> Self {
+        self.wrap = wrap;
+        self
+    }
 }
 
-impl Texture for ImageTexture {
-    fn value(&self, u: f64, v: f64, _: Point3) -> Color {
-        // Clamp input texture coordinates to [0,1] x [1,0]
-        let u = Interval::new(0., 1.).clamp(u);
+impl ImageTexture {
+    fn pixel(&self, u: f64, v: f64, footprint: f64) -> [f64; 4] {
+        // Filtered RGBA at the texture coordinates, where footprint is the width of the area to
+        // average in texture coordinates, or 0 for a single point.
         // Flip V to image coordinates
-        let v = 1.0 - Interval::new(0., 1.).clamp(v);
-
-        let i = (u * self.image.width() as f64) as i32;
-        let j = (v * self.image.height() as f64) as i32;
-        let pixel = self.image.pixel_data(i, j);
-
-        let color_scale = 1.0 / 255.0;
-        Color::new(
-            color_scale * pixel[0] as f64,
-            color_scale * pixel[1] as f64,
-            color_scale * pixel[2] as f64,
-        )
+        let v = 1.0 - v;
+
+        match self.filter {
+            Filter::Nearest => {
+                let (width, height) = self.image.level_size(0);
+                let i = (u * width as f64).floor() as i32;
+                let j = (v * height as f64).floor() as i32;
+                self.texel(0, i, j)
+            }
+            Filter::Bilinear => self.bilinear(0, u, v),
+            Filter::Bicubic => self.bicubic(u, v),
+            Filter::Trilinear => {
+                let (width, height) = self.image.level_size(0);
+                let max_level = (self.image.mip_levels() - 1) as f64;
+                let lod = (footprint * width.max(height) as f64)
+                    .max(1.)
+                    .log2()
+                    .min(max_level);
+                let level = lod.floor() as usize;
+                let t = lod - level as f64;
+
+                let finer = self.bilinear(level, u, v);
+                if t == 0. {
+                    return finer;
+                }
+                let coarser = self.bilinear(level + 1, u, v);
+                [0, 1, 2, 3].map(|c| (1. - t) * finer[c] + t * coarser[c])
+            }
+        }
+    }
+
+    fn texel(&self, level: usize, i: i32, j: i32) -> [f64; 4] {
+        let (width, height) = self.image.level_size(level);
+        let i = self.wrap.wrap(i, width);
+        let j = self.wrap.wrap(j, height);
+        self.image.texel(level, i, j).map(f64::from)
+    }
+
+    fn bilinear(&self, level: usize, u: f64, v: f64) -> [f64; 4] {
+        // Pixel centers sit halfway between integer image coordinates.
+        let (width, height) = self.image.level_size(level);
+        let x = u * width as f64 - 0.5;
+        let y = v * height as f64 - 0.5;
+        let (i, j) = (x.floor() as i32, y.floor() as i32);
+        let (tx, ty) = (x - x.floor(), y - y.floor());
+
+        let mut sum = [0.; 4];
+        for (di, dj, weight) in [
+            (0, 0, (1. - tx) * (1. - ty)),
+            (1, 0, tx * (1. - ty)),
+            (0, 1, (1. - tx) * ty),
+            (1, 1, tx * ty),
+        ] {
+            let pixel = self.texel(level, i + di, j + dj);
+            sum.iter_mut()
+                .zip(pixel)
+                .for_each(|(s, p)| *s += weight * p);
+        }
+        sum
+    }
+
+    fn bicubic(&self, u: f64, v: f64) -> [f64; 4] {
+        let (width, height) = self.image.level_size(0);
+        let x = u * width as f64 - 0.5;
+        let y = v * height as f64 - 0.5;
+        let (i, j) = (x.floor() as i32, y.floor() as i32);
+        let wx = catmull_rom_weights(x - x.floor());
+        let wy = catmull_rom_weights(y - y.floor());
+
+        let mut sum = [0.; 4];
+        for (dj, weight_y) in (-1..=2).zip(wy) {
+            for (di, weight_x) in (-1..=2).zip(wx) {
+                let pixel = self.texel(0, i + di, j + dj);
+                let weight = weight_x * weight_y;
+                sum.iter_mut()
+                    .zip(pixel)
+                    .for_each(|(s, p)| *s += weight * p);
+            }
+        }
+
+        // The spline overshoots around sharp edges.
+        [
+            sum[0].max(0.),
+            sum[1].max(0.),
+            sum[2].max(0.),
+            sum[3].clamp(0., 1.),
+        ]
+    }
+}
+
+fn catmull_rom_weights(t: f64) -> [f64; 4] {
+    // Weights of the four pixels around a point t of the way from the second to the third.
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+        0.5 * (-t3 + 2. * t2 - t),
+        0.5 * (3. * t3 - 5. * t2 + 2.),
+        0.5 * (-3. * t3 + 4. * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        self.filtered_value(u, v, p, 0.)
+    }
+
+    fn filtered_value(&self, u: f64, v: f64, _: Point3, footprint: f64) -> Color {
+        let pixel = self.pixel(u, v, footprint);
+        Color::new(pixel[0], pixel[1], pixel[2])
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: Point3) -> f64 {
+        self.filtered_alpha(u, v, p, 0.)
+    }
+
+    fn filtered_alpha(&self, u: f64, v: f64, _: Point3, footprint: f64) -> f64 {
+        self.pixel(u, v, footprint)[3]
     }
 }
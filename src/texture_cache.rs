@@ -0,0 +1,589 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs, mem,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, RwLock,
+    },
+};
+
+use image::{io::Reader as ImageReader, ImageError, ImageResult};
+
+use crate::rtw_image::{self, ColorSpace, DecodedImage, RtwImage, CHANNELS_PER_PIXEL};
+
+// Width and height in pixels of the square blocks images are sampled from.
+const TILE_SIZE: i32 = 64;
+// Memory budget for the tiles and decoded files of the cache shared by the whole program.
+const DEFAULT_BUDGET: usize = 512 << 20;
+// Share of the budget eviction frees memory down to, in tenths. Freeing more than needed means the
+// cache is only sorted by age once in a while rather than on every load once it's full.
+const LOW_WATER_TENTHS: usize = 9;
+// Number of tiles each thread remembers using last, which it reads without locking.
+const RECENT_TILES: usize = 4;
+
+// Shared store for the images of textures. Each file is opened once however many textures use it,
+// and decoded at the bit depth it's stored in when it's first sampled. Lookups read tiles of linear
+// floats cut from it as they're needed, with the mip levels filtered down a tile at a time. Once
+// the tiles and decoded files together take up more than the budget the least recently used of
+// them are dropped, and made again if they are needed later.
+#[derive(Clone)]
+pub struct TextureCache {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    // Tells caches apart in the tiles threads remember
+    id: usize,
+    // Bytes of pixel data the tiles and decoded files may take up
+    budget: usize,
+    sources: Mutex<Sources>,
+    tiles: RwLock<Tiles>,
+    // Counts the loads, which tiles are stamped with when they are used
+    epoch: AtomicU64,
+    // Files that failed to decode while sampling, until they are taken
+    errors: Mutex<Vec<(PathBuf, ImageError)>>,
+}
+
+#[derive(Default)]
+struct Sources {
+    images: Vec<Arc<Source>>,
+    // Index of each image by its file and color space
+    ids: HashMap<(PathBuf, ColorSpace), usize>,
+}
+
+struct Source {
+    path: PathBuf,
+    color_space: ColorSpace,
+    // Width and height of each mip level, from the full image down to a single pixel
+    level_sizes: Arc<[(i32, i32)]>,
+    decoded: Mutex<Decoded>,
+    // Load the decoded file was last used in
+    last_used: AtomicU64,
+}
+
+enum Decoded {
+    Unloaded,
+    // Held in memory until it's evicted
+    Loaded(Arc<DecodedImage>),
+    // Decoding failed, so it isn't tried again
+    Failed,
+}
+
+#[derive(Default)]
+struct Tiles {
+    // Tile slots of each image by its index, filled in once its first tile is added
+    images: Vec<Vec<LevelTiles>>,
+    // Sources holding on to their decoded file
+    decoded: Vec<Arc<Source>>,
+    // Bytes of pixel data in all tiles and decoded files
+    size: usize,
+}
+
+// Something held in the cache that can be evicted.
+#[derive(Clone, Copy)]
+enum Resident {
+    Tile(TileKey),
+    // Index of the source in Tiles::decoded
+    Decoded(usize),
+}
+
+// The tiles of a mip level, row by row.
+struct LevelTiles {
+    columns: i32,
+    slots: Vec<Option<Arc<Tile>>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct TileKey {
+    image: usize,
+    level: usize,
+    // Position in tiles from the top left of the mip level
+    x: i32,
+    y: i32,
+}
+
+struct Tile {
+    // Linear RGBA values, row by row
+    data: Box<[f32]>,
+    width: i32,
+    last_used: AtomicU64,
+}
+
+// The tiles the current thread used last, along with the id of their cache.
+#[derive(Default)]
+struct RecentTiles {
+    entries: [Option<(usize, TileKey, Arc<Tile>)>; RECENT_TILES],
+    // Entry replaced next
+    next: usize,
+}
+
+thread_local! {
+    static RECENT: RefCell<RecentTiles> = RefCell::default();
+}
+
+impl TextureCache {
+    pub fn new(budget: usize) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        Self {
+            shared: Arc::new(Shared {
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                budget,
+                sources: Mutex::default(),
+                tiles: RwLock::default(),
+                epoch: AtomicU64::new(0),
+                errors: Mutex::default(),
+            }),
+        }
+    }
+
+    pub fn global() -> &'static Self {
+        static GLOBAL: OnceLock<TextureCache> = OnceLock::new();
+        GLOBAL.get_or_init(|| Self::new(DEFAULT_BUDGET))
+    }
+
+    pub fn image(
+        &self,
+        filename: impl AsRef<Path>,
+        color_space: ColorSpace,
+    ) -> ImageResult<RtwImage> {
+        // Only the header is read for now, which is enough to catch files that are missing or in
+        // an unsupported format. Errors in the rest of the file show up in take_errors once the
+        // image is sampled.
+        let key = (fs::canonicalize(filename)?, color_space);
+        let mut sources = self.shared.sources.lock().unwrap();
+        let id = match sources.ids.get(&key) {
+            Some(&id) => id,
+            None => {
+                let (width, height) = ImageReader::open(&key.0)?.into_dimensions()?;
+                sources.images.push(Arc::new(Source {
+                    path: key.0.clone(),
+                    color_space,
+                    level_sizes: rtw_image::mip_level_sizes(width as i32, height as i32).into(),
+                    decoded: Mutex::new(Decoded::Unloaded),
+                    last_used: AtomicU64::new(0),
+                }));
+                let id = sources.images.len() - 1;
+                sources.ids.insert(key, id);
+                id
+            }
+        };
+
+        let level_sizes = sources.images[id].level_sizes.clone();
+        Ok(RtwImage::from_cache(self.clone(), id, level_sizes))
+    }
+
+    // The files that failed to decode since the last call, which then read as black.
+    pub fn take_errors(&self) -> Vec<(PathBuf, ImageError)> {
+        mem::take(&mut self.shared.errors.lock().unwrap())
+    }
+
+    pub fn texel(&self, image: usize, level: usize, x: i32, y: i32) -> [f32; 4] {
+        // Return the linear RGBA values of the pixel at x,y of the given mip level of an image,
+        // which must lie inside it.
+        let key = TileKey {
+            image,
+            level,
+            x: x / TILE_SIZE,
+            y: y / TILE_SIZE,
+        };
+        let cache = self.shared.id;
+        let epoch = self.shared.epoch.load(Ordering::Relaxed);
+
+        // Most lookups land in a tile this thread used just before, which needs no locking.
+        let recent = RECENT.with(|recent| {
+            let recent = recent.borrow();
+            recent.get(cache, key).map(|tile| tile.texel(x, y, epoch))
+        });
+        if let Some(texel) = recent {
+            return texel;
+        }
+
+        let tile = self.tile(key);
+        RECENT.with(|recent| recent.borrow_mut().insert(cache, key, tile.clone()));
+        tile.texel(x, y, epoch)
+    }
+
+    fn tile(&self, key: TileKey) -> Arc<Tile> {
+        let found = self.shared.tiles.read().unwrap().get(key).cloned();
+        found.unwrap_or_else(|| self.load(key))
+    }
+
+    fn load(&self, key: TileKey) -> Arc<Tile> {
+        // Make the tile without holding any locks, cutting it from the decoded file for the full
+        // size level and filtering it down from the level above for the others. Only adding it
+        // takes the lock, which then drops the least recently used tiles and decoded files until
+        // they take up well under the budget again, except for the tile asked for.
+        let source = self.shared.sources.lock().unwrap().images[key.image].clone();
+        let epoch = self.shared.epoch.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let (level_width, level_height) = source.level_sizes[key.level];
+        let (x0, y0) = (key.x * TILE_SIZE, key.y * TILE_SIZE);
+        let width = TILE_SIZE.min(level_width - x0);
+        let height = TILE_SIZE.min(level_height - y0);
+        let tile = if key.level == 0 {
+            let decoded = self.decode(&source, epoch);
+            Tile::new(width, height, epoch, |x, y| {
+                let image = decoded.as_ref();
+                image.map_or([0.; 4], |image| image.pixel(x0 + x, y0 + y))
+            })
+        } else {
+            // Box filter the pixels of the level above that each pixel covers. They come from the
+            // tiles above this one, which are held on to while it's made.
+            let level = key.level - 1;
+            let (above_width, above_height) = source.level_sizes[level];
+            let taps = |start, length, above, size| -> Vec<_> {
+                let pixels = start..start + length;
+                pixels
+                    .map(|i| rtw_image::downsample_taps(i, above, size))
+                    .collect()
+            };
+            let xs = taps(x0, width, above_width, level_width);
+            let ys = taps(y0, height, above_height, level_height);
+            let tile_span = |taps: &[[(i32, f32); 4]]| {
+                let used = taps.iter().flatten().filter(|&&(_, weight)| weight > 0.);
+                let pixels = used.map(|&(p, _)| p / TILE_SIZE);
+                let (first, last) = pixels.fold((i32::MAX, 0), |(a, b), p| (a.min(p), b.max(p)));
+                (first, last - first + 1)
+            };
+            let (column, columns) = tile_span(&xs);
+            let (row, rows) = tile_span(&ys);
+            let above: Vec<_> = (0..rows * columns)
+                .map(|i| {
+                    let (x, y) = (column + i % columns, row + i / columns);
+                    self.tile(TileKey { level, x, y, ..key })
+                })
+                .collect();
+
+            Tile::new(width, height, epoch, |x, y| {
+                let mut sum = [0.; 4];
+                for &(sy, wy) in &ys[y as usize] {
+                    for &(sx, wx) in &xs[x as usize] {
+                        if wx > 0. && wy > 0. {
+                            let i = (sy / TILE_SIZE - row) * columns + sx / TILE_SIZE - column;
+                            let pixel = above[i as usize].texel(sx, sy, epoch);
+                            sum.iter_mut()
+                                .zip(pixel)
+                                .for_each(|(s, p)| *s += p * wx * wy);
+                        }
+                    }
+                }
+                sum
+            })
+        };
+
+        let mut tiles = self.shared.tiles.write().unwrap();
+        if let Some(tile) = tiles.get(key) {
+            // Another thread made it in the meantime.
+            return tile.clone();
+        }
+        let tile = Arc::new(tile);
+        tiles.insert(key, &source.level_sizes, tile.clone());
+        tiles.evict(self.shared.budget, Some(key));
+        tile
+    }
+
+    fn decode(&self, source: &Arc<Source>, epoch: u64) -> Option<Arc<DecodedImage>> {
+        // Decode the file unless it's still held from before. Threads wanting the same file wait
+        // for it, while the rest carry on.
+        let mut decoded = source.decoded.lock().unwrap();
+        source.last_used.store(epoch, Ordering::Relaxed);
+        match &*decoded {
+            Decoded::Loaded(image) => return Some(image.clone()),
+            Decoded::Failed => return None,
+            Decoded::Unloaded => {}
+        }
+
+        match rtw_image::decode(&source.path, source.color_space) {
+            Ok(image) => {
+                let image = Arc::new(image);
+                *decoded = Decoded::Loaded(image.clone());
+                drop(decoded);
+
+                let mut tiles = self.shared.tiles.write().unwrap();
+                tiles.size += image.bytes();
+                tiles.decoded.push(source.clone());
+                tiles.evict(self.shared.budget, None);
+                Some(image)
+            }
+            Err(error) => {
+                let mut errors = self.shared.errors.lock().unwrap();
+                errors.push((source.path.clone(), error));
+                *decoded = Decoded::Failed;
+                None
+            }
+        }
+    }
+}
+
+impl Tiles {
+    fn get(&self, key: TileKey) -> Option<&Arc<Tile>> {
+        let level = self.images.get(key.image)?.get(key.level)?;
+        level.slots[(key.y * level.columns + key.x) as usize].as_ref()
+    }
+
+    fn insert(&mut self, key: TileKey, level_sizes: &[(i32, i32)], tile: Arc<Tile>) {
+        // Make the slots for all levels of the image along with its first tile.
+        if self.images.len() <= key.image {
+            self.images.resize_with(key.image + 1, Vec::new);
+        }
+        let levels = &mut self.images[key.image];
+        if levels.is_empty() {
+            *levels = level_sizes
+                .iter()
+                .map(|&(width, height)| {
+                    let columns = (width + TILE_SIZE - 1) / TILE_SIZE;
+                    let rows = (height + TILE_SIZE - 1) / TILE_SIZE;
+                    LevelTiles {
+                        columns,
+                        slots: vec![None; (columns * rows) as usize],
+                    }
+                })
+                .collect();
+        }
+
+        let level = &mut levels[key.level];
+        self.size += tile.bytes();
+        level.slots[(key.y * level.columns + key.x) as usize] = Some(tile);
+    }
+
+    fn evict(&mut self, budget: usize, keep: Option<TileKey>) {
+        if self.size <= budget {
+            return;
+        }
+
+        let mut by_age = Vec::new();
+        for (image, levels) in self.images.iter().enumerate() {
+            for (level, tiles) in levels.iter().enumerate() {
+                for (i, slot) in tiles.slots.iter().enumerate() {
+                    if let Some(tile) = slot {
+                        let (x, y) = (i as i32 % tiles.columns, i as i32 / tiles.columns);
+                        let key = TileKey { image, level, x, y };
+                        let last_used = tile.last_used.load(Ordering::Relaxed);
+                        by_age.push((last_used, Resident::Tile(key)));
+                    }
+                }
+            }
+        }
+        for (i, source) in self.decoded.iter().enumerate() {
+            let last_used = source.last_used.load(Ordering::Relaxed);
+            by_age.push((last_used, Resident::Decoded(i)));
+        }
+        by_age.sort_unstable_by_key(|&(last_used, _)| last_used);
+
+        let low_water = budget / 10 * LOW_WATER_TENTHS;
+        let mut unloaded = vec![false; self.decoded.len()];
+        for (_, resident) in by_age {
+            if self.size <= low_water {
+                break;
+            }
+            match resident {
+                Resident::Tile(key) if Some(key) != keep => {
+                    let level = &mut self.images[key.image][key.level];
+                    let tile = level.slots[(key.y * level.columns + key.x) as usize].take();
+                    self.size -= tile.map_or(0, |tile| tile.bytes());
+                }
+                Resident::Tile(_) => {}
+                Resident::Decoded(i) => {
+                    // A file another thread is decoding right now is skipped rather than waited
+                    // for with the lock held.
+                    let Ok(mut decoded) = self.decoded[i].decoded.try_lock() else {
+                        continue;
+                    };
+                    if let Decoded::Loaded(image) = mem::replace(&mut *decoded, Decoded::Unloaded) {
+                        self.size -= image.bytes();
+                    }
+                    unloaded[i] = true;
+                }
+            }
+        }
+
+        let mut unloaded = unloaded.into_iter();
+        self.decoded.retain(|_| !unloaded.next().unwrap());
+    }
+}
+
+impl Tile {
+    fn new(
+        width: i32,
+        height: i32,
+        last_used: u64,
+        mut pixel: impl FnMut(i32, i32) -> [f32; 4],
+    ) -> Self {
+        // Fill in the tile with the pixel at each x,y within it. Tiles along the right and bottom
+        // edges of a level are smaller when its size isn't a multiple of the tile size.
+        let mut data = Vec::with_capacity((width * height * CHANNELS_PER_PIXEL) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                data.extend(pixel(x, y));
+            }
+        }
+
+        Self {
+            data: data.into(),
+            width,
+            last_used: AtomicU64::new(last_used),
+        }
+    }
+
+    fn texel(&self, x: i32, y: i32, epoch: u64) -> [f32; 4] {
+        // Stamping only when the epoch changed keeps threads from writing to shared tiles on every
+        // lookup.
+        if self.last_used.load(Ordering::Relaxed) != epoch {
+            self.last_used.store(epoch, Ordering::Relaxed);
+        }
+
+        let (x, y) = (x % TILE_SIZE, y % TILE_SIZE);
+        self.data[((y * self.width + x) * CHANNELS_PER_PIXEL) as usize..][..4]
+            .try_into()
+            .unwrap()
+    }
+
+    fn bytes(&self) -> usize {
+        self.data.len() * mem::size_of::<f32>()
+    }
+}
+
+impl RecentTiles {
+    fn get(&self, cache: usize, key: TileKey) -> Option<&Arc<Tile>> {
+        self.entries
+            .iter()
+            .flatten()
+            .find(|&&(c, k, _)| c == cache && k == key)
+            .map(|(_, _, tile)| tile)
+    }
+
+    fn insert(&mut self, cache: usize, key: TileKey, tile: Arc<Tile>) {
+        self.entries[self.next] = Some((cache, key, tile));
+        self.next = (self.next + 1) % RECENT_TILES;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Room for a few tiles along with the decoded test image, so that reading it all evicts.
+    const BUDGET: usize =
+        4 * (TILE_SIZE * TILE_SIZE * CHANNELS_PER_PIXEL) as usize * mem::size_of::<f32>();
+
+    // Odd sized, spanning partial tiles and halving unevenly further down.
+    const WIDTH: u32 = 200;
+    const HEIGHT: u32 = 130;
+
+    fn test_image(name: &str) -> (TextureCache, DecodedImage, PathBuf) {
+        let path = std::env::temp_dir().join(format!("{name}_{}.png", std::process::id()));
+        let image = image::RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+            image::Rgba([(x * 7) as u8, (y * 13) as u8, (x ^ y) as u8, (x + y) as u8])
+        });
+        image.save(&path).unwrap();
+
+        let cache = TextureCache::new(BUDGET);
+        cache.image(&path, ColorSpace::Srgb).unwrap();
+        let decoded = rtw_image::decode(&path, ColorSpace::Srgb).unwrap();
+        (cache, decoded, path)
+    }
+
+    fn level_sizes(cache: &TextureCache) -> Arc<[(i32, i32)]> {
+        cache.shared.sources.lock().unwrap().images[0]
+            .level_sizes
+            .clone()
+    }
+
+    fn assert_close(a: [f32; 4], b: [f32; 4]) {
+        let close = a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5);
+        assert!(close, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn texels_match_decoded_pixels() {
+        let (cache, decoded, path) = test_image("texels_match_decoded_pixels");
+        for y in 0..HEIGHT as i32 {
+            for x in 0..WIDTH as i32 {
+                assert_eq!(cache.texel(0, 0, x, y), decoded.pixel(x, y));
+            }
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn eviction_keeps_within_budget() {
+        let (cache, _, path) = test_image("eviction_keeps_within_budget");
+        for (level, &(width, height)) in level_sizes(&cache).iter().enumerate() {
+            for y in 0..height {
+                for x in 0..width {
+                    cache.texel(0, level, x, y);
+                    assert!(cache.shared.tiles.read().unwrap().size <= BUDGET);
+                }
+            }
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn evicted_tiles_reload_the_same() {
+        let (cache, _, path) = test_image("evicted_tiles_reload_the_same");
+        let first_tile = |cache: &TextureCache| -> Vec<_> {
+            let pixels = (0..TILE_SIZE).flat_map(|y| (0..TILE_SIZE).map(move |x| (x, y)));
+            pixels.map(|(x, y)| cache.texel(0, 0, x, y)).collect()
+        };
+        let before = first_tile(&cache);
+
+        for y in 0..HEIGHT as i32 {
+            for x in 0..WIDTH as i32 {
+                cache.texel(0, 0, x, y);
+            }
+        }
+        let key = TileKey {
+            image: 0,
+            level: 0,
+            x: 0,
+            y: 0,
+        };
+        assert!(cache.shared.tiles.read().unwrap().get(key).is_none());
+
+        assert_eq!(first_tile(&cache), before);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn mip_levels_average_the_level_above() {
+        let (cache, _, path) = test_image("mip_levels_average_the_level_above");
+        let level_sizes = level_sizes(&cache);
+
+        // Even sized levels average each 2x2 block.
+        assert_eq!(level_sizes[1], (WIDTH as i32 / 2, HEIGHT as i32 / 2));
+        for y in 0..level_sizes[1].1 {
+            for x in 0..level_sizes[1].0 {
+                let mut average = [0.; 4];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let pixel = cache.texel(0, 0, 2 * x + dx, 2 * y + dy);
+                    average
+                        .iter_mut()
+                        .zip(pixel)
+                        .for_each(|(a, p)| *a += p / 4.);
+                }
+                assert_close(cache.texel(0, 1, x, y), average);
+            }
+        }
+
+        // Odd sized ones spread over three pixels, with every level averaging to the same.
+        let mean = |level: usize| {
+            let (width, height) = level_sizes[level];
+            let mut sum = [0f64; 4];
+            for y in 0..height {
+                for x in 0..width {
+                    let pixel = cache.texel(0, level, x, y);
+                    sum.iter_mut().zip(pixel).for_each(|(s, p)| *s += p as f64);
+                }
+            }
+            sum.map(|s| (s / (width * height) as f64) as f32)
+        };
+        for level in 1..level_sizes.len() {
+            assert_close(mean(level), mean(level - 1));
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{AnyHittable, HitRecord, Hittable},
+    interval::Interval,
+    quat::Quat,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+#[derive(Clone, Copy)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub fn new(translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn interpolate(self, other: Self, t: f64) -> Self {
+        // Translation and scale are interpolated linearly, rotation along the shortest arc.
+        Self::new(
+            self.translation + t * (other.translation - self.translation),
+            self.rotation.slerp(other.rotation, t),
+            self.scale + t * (other.scale - self.scale),
+        )
+    }
+
+    pub fn apply_point(self, p: Point3) -> Point3 {
+        self.rotation.rotate(self.scale * p) + self.translation
+    }
+
+    pub fn apply_vector(self, v: Vec3) -> Vec3 {
+        self.rotation.rotate(self.scale * v)
+    }
+
+    pub fn apply_normal(self, n: Vec3) -> Vec3 {
+        // Normals transform by the inverse transpose, which for a rotation is the rotation itself.
+        self.rotation.rotate(n / self.scale)
+    }
+
+    pub fn inverse_point(self, p: Point3) -> Point3 {
+        self.inverse_vector(p - self.translation)
+    }
+
+    pub fn inverse_vector(self, v: Vec3) -> Vec3 {
+        self.rotation.conjugate().rotate(v) / self.scale
+    }
+}
+
+#[derive(Clone)]
+pub struct MotionTransform {
+    object: Arc<AnyHittable>,
+    // Transforms at shutter open (time 0) and shutter close (time 1)
+    start: Transform,
+    end: Transform,
+    bbox: Aabb,
+}
+
+impl MotionTransform {
+    pub fn new(object: impl Into<AnyHittable>, start: Transform, end: Transform) -> Self {
+        let object = object.into();
+        let bbox = Self::swept_bounding_box(object.bounding_box(), start, end);
+
+        Self {
+            object: Arc::new(object),
+            start,
+            end,
+            bbox,
+        }
+    }
+
+    fn swept_bounding_box(bbox: Aabb, start: Transform, end: Transform) -> Aabb {
+        if start.rotation.dot(end.rotation).abs() > 1. - 1e-12 {
+            // Without rotation every point moves along a straight line, so the boxes around the
+            // transformed corners at both ends of the motion enclose the whole sweep.
+            let mut swept = Aabb::default();
+            for xf in [start, end] {
+                for corner in bbox.corners() {
+                    let p = xf.apply_point(corner);
+                    swept = Aabb::from_aabs(swept, Aabb::from_points(p, p));
+                }
+            }
+            return swept;
+        }
+
+        // With rotation, bound the object by a sphere around its local center. The sphere radius
+        // is convex in time, so its largest value is reached at one end of the motion, and the
+        // sphere center stays within that radius of the interpolated translation.
+        let center = 0.5 * (bbox.min() + bbox.max());
+        let radius = 0.5 * (bbox.max() - bbox.min()).length();
+        let reach = |xf: Transform| {
+            let max_scale = xf
+                .scale
+                .x()
+                .abs()
+                .max(xf.scale.y().abs())
+                .max(xf.scale.z().abs());
+            (xf.scale * center).length() + max_scale * radius
+        };
+        let reach = reach(start).max(reach(end));
+        let rvec = Vec3::new(reach, reach, reach);
+
+        Aabb::from_aabs(
+            Aabb::from_points(start.translation - rvec, start.translation + rvec),
+            Aabb::from_points(end.translation - rvec, end.translation + rvec),
+        )
+    }
+}
+
+impl Hittable for MotionTransform {
+    fn hit(&self, r: Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        // Transform the ray into object space at the ray's time. The direction is not normalized,
+        // so the hit distance t is the same in both spaces.
+        let xf = self.start.interpolate(self.end, r.time());
+        let object_r = Ray::new(
+            xf.inverse_point(r.origin()),
+            xf.inverse_vector(r.direction()),
+            r.time(),
+        )
+        .with_differentials(
+            r.differentials()
+                .map(|d| d.transformed(|p| xf.inverse_point(p), |v| xf.inverse_vector(v))),
+        );
+
+        let mut rec = self.object.hit(object_r, ray_t)?;
+
+        // Transform the intersection back to world space.
+        rec.p = xf.apply_point(rec.p);
+        let normal = xf.apply_normal(rec.normal);
+        rec.normal = normal.unit_vector();
+        rec.dpdu = xf.apply_vector(rec.dpdu);
+        rec.dpdv = xf.apply_vector(rec.dpdv);
+        // The normal is transformed by the inverse transpose and then normalized, so its
+        // derivatives are transformed the same way, less the part along the normal that the
+        // normalization takes out.
+        let n = rec.normal;
+        let length = normal.length();
+        let dn = |d: Vec3| {
+            let d = xf.apply_normal(d);
+            (d - d.dot(n) * n) / length
+        };
+        rec.dndu = dn(rec.dndu);
+        rec.dndv = dn(rec.dndv);
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::{AnyMaterial, Material},
+    mesh::Mesh,
+    ray::Ray,
+};
+
+// A triangle of a mesh.
+#[derive(Clone)]
+pub struct Triangle {
+    mesh: Arc<Mesh>,
+    face: usize,
+    mat: Arc<AnyMaterial>,
+    bbox: Aabb,
+}
+
+impl Triangle {
+    pub fn new(mesh: Arc<Mesh>, face: usize, mat: Arc<AnyMaterial>) -> Self {
+        let [a, b, c] = mesh.vertices(face);
+        let bbox = Aabb::from_aabs(
+            Aabb::from_points(mesh.position(a), mesh.position(b)),
+            Aabb::from_points(mesh.position(c), mesh.position(c)),
+        )
+        .pad_to_minimums();
+
+        Self {
+            mesh,
+            face,
+            mat,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        // Möller–Trumbore intersection, solving for the hit distance and the barycentric
+        // coordinates b1 and b2 of the second and third vertex.
+        let [i0, i1, i2] = self.mesh.vertices(self.face);
+        let (p0, p1, p2) = (
+            self.mesh.position(i0),
+            self.mesh.position(i1),
+            self.mesh.position(i2),
+        );
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+
+        let pvec = r.direction().cross(e2);
+        let det = e1.dot(pvec);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1. / det;
+
+        let tvec = r.origin() - p0;
+        let b1 = tvec.dot(pvec) * inv_det;
+        if !(0. ..=1.).contains(&b1) {
+            return None;
+        }
+        let qvec = tvec.cross(e1);
+        let b2 = r.direction().dot(qvec) * inv_det;
+        if b2 < 0. || b1 + b2 > 1. {
+            return None;
+        }
+        let t = e2.dot(qvec) * inv_det;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+        let b0 = 1. - b1 - b2;
+
+        let (uv0, uv1, uv2) = (self.mesh.uv(i0), self.mesh.uv(i1), self.mesh.uv(i2));
+        let u = b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0;
+        let v = b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1;
+        let p = r.at(t);
+
+        // Shade with the interpolated vertex normals, but tell the sides apart by the geometric
+        // normal, turned to the same side so the winding order doesn't matter.
+        let shading_normal =
+            (b0 * self.mesh.normal(i0) + b1 * self.mesh.normal(i1) + b2 * self.mesh.normal(i2))
+                .unit_vector();
+        let geometric_normal = e1.cross(e2).unit_vector();
+        let geometric_normal = if geometric_normal.dot(shading_normal) < 0. {
+            -geometric_normal
+        } else {
+            geometric_normal
+        };
+
+        let mut rec = HitRecord::new(r, p, geometric_normal, &self.mat, t, u, v);
+        rec.normal = if rec.front_face {
+            shading_normal
+        } else {
+            -shading_normal
+        };
+
+        // Tangents from how the texture coordinates change along the edges.
+        let (du02, dv02) = (uv0.0 - uv2.0, uv0.1 - uv2.1);
+        let (du12, dv12) = (uv1.0 - uv2.0, uv1.1 - uv2.1);
+        let uv_det = du02 * dv12 - dv02 * du12;
+        if uv_det.abs() > 1e-12 {
+            let (dp02, dp12) = (p0 - p2, p1 - p2);
+            rec.dpdu = (dv12 * dp02 - dv02 * dp12) / uv_det;
+            rec.dpdv = (du02 * dp12 - du12 * dp02) / uv_det;
+
+            // The same for the vertex normals, which curve the surface seen by reflections.
+            let n2 = self.mesh.normal(i2);
+            let (dn02, dn12) = (self.mesh.normal(i0) - n2, self.mesh.normal(i1) - n2);
+            rec.dndu = (dv12 * dn02 - dv02 * dn12) / uv_det;
+            rec.dndv = (du02 * dn12 - du12 * dn02) / uv_det;
+        }
+        if !self.mat.is_opaque(r, &rec) {
+            return None;
+        }
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
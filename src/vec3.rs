@@ -207,3 +207,11 @@ impl Div<f64> for Vec3 {
         (1. / rhs) * self
     }
 }
+
+impl Div for Vec3 {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        Vec3::new(self[0] / rhs[0], self[1] / rhs[1], self[2] / rhs[2])
+    }
+}